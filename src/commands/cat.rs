@@ -0,0 +1,30 @@
+use anyhow::{bail, Result};
+use std::io::Write;
+use std::path::Path;
+
+use crate::core::store::Store;
+use crate::utils::compression::decompress;
+
+/// Writes one file from the snapshot to stdout.
+pub fn cat(db: &str, path: &str) -> Result<()> {
+    let db_path = Path::new(db);
+
+    if !db_path.exists() {
+        bail!("Database not found: {}", db_path.display());
+    }
+
+    let store = Store::open_readonly(db_path.to_str().unwrap_or_default())?;
+
+    let Some(file) = store.find_file(path)? else {
+        bail!("File not found in snapshot: {}", path);
+    };
+    let Some(compressed) = store.get_blob(&file.record.blob_hash)? else {
+        bail!("Blob missing for {}: {}", path, file.record.blob_hash);
+    };
+
+    let mut stdout = std::io::stdout().lock();
+    stdout.write_all(&decompress(&compressed)?)?;
+    stdout.flush()?;
+
+    Ok(())
+}
@@ -0,0 +1,261 @@
+use anyhow::{bail, Result};
+use semver::Version;
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+
+use crate::commands::pack::print_box;
+use crate::core::differ::{diff_files, match_packages, FileChanges, PackageChange};
+use crate::core::store::Store;
+use crate::types::{DiffOptions, PackageInfo};
+use crate::utils::compression::decompress;
+use crate::utils::diff::{print_file_diff, unified_diff, FileDiff, MAX_DIFF_SIZE};
+
+/// `relative_path -> blob hash` for every package id.
+type PackageFiles = HashMap<i64, BTreeMap<String, String>>;
+
+/// One of the two DBs being compared.
+struct Side {
+    store: Store,
+    files: PackageFiles,
+}
+
+impl Side {
+    fn open(db: &str) -> Result<Self> {
+        let db_path = Path::new(db);
+        if !db_path.exists() {
+            bail!("Database not found: {}", db_path.display());
+        }
+
+        let store = Store::open_readonly(db_path.to_str().unwrap_or_default())?;
+        let mut files: PackageFiles = HashMap::new();
+        for file in store.get_all_files()? {
+            files
+                .entry(file.record.package_id)
+                .or_default()
+                .insert(file.record.relative_path, file.record.blob_hash);
+        }
+
+        Ok(Side { store, files })
+    }
+
+    fn files_of(&self, pkg: &PackageInfo) -> &BTreeMap<String, String> {
+        static EMPTY: BTreeMap<String, String> = BTreeMap::new();
+        pkg.id.and_then(|id| self.files.get(&id)).unwrap_or(&EMPTY)
+    }
+
+    /// Uncompressed size of a package's file, 0 when it has none.
+    fn size(&self, pkg: &PackageInfo, path: &str) -> Result<u64> {
+        match self.files_of(pkg).get(path) {
+            Some(hash) => Ok(self.store.get_blob_size(hash)?.unwrap_or_default()),
+            None => Ok(0),
+        }
+    }
+
+    fn read(&self, pkg: &PackageInfo, path: &str) -> Result<Vec<u8>> {
+        let Some(hash) = self.files_of(pkg).get(path) else {
+            return Ok(Vec::new());
+        };
+        match self.store.get_blob(hash)? {
+            Some(compressed) => decompress(&compressed),
+            None => bail!("Blob missing for {}: {}", path, hash),
+        }
+    }
+}
+
+#[derive(Default)]
+struct DiffCounts {
+    added: usize,
+    removed: usize,
+    upgraded: usize,
+    downgraded: usize,
+    changed: usize,
+    moved: usize,
+    files_added: usize,
+    files_removed: usize,
+    files_modified: usize,
+}
+
+pub fn diff(options: &DiffOptions) -> Result<()> {
+    let old_side = Side::open(&options.old)?;
+    let new_side = Side::open(&options.new)?;
+
+    let old_packages = old_side.store.get_packages()?;
+    let new_packages = new_side.store.get_packages()?;
+
+    let mut changes = match_packages(&old_packages, &new_packages);
+    changes.sort_by(|a, b| {
+        change_path(a, &old_packages, &new_packages).cmp(change_path(
+            b,
+            &old_packages,
+            &new_packages,
+        ))
+    });
+
+    let mut counts = DiffCounts::default();
+
+    for change in &changes {
+        match *change {
+            PackageChange::Added(j) => {
+                let pkg = &new_packages[j];
+                counts.added += 1;
+                println!(
+                    "+ {}@{} ({}, {} files)",
+                    pkg.name,
+                    pkg.version,
+                    pkg.path,
+                    new_side.files_of(pkg).len()
+                );
+            }
+            PackageChange::Removed(i) => {
+                let pkg = &old_packages[i];
+                counts.removed += 1;
+                println!(
+                    "- {}@{} ({}, {} files)",
+                    pkg.name,
+                    pkg.version,
+                    pkg.path,
+                    old_side.files_of(pkg).len()
+                );
+            }
+            PackageChange::Kept { old, new } => {
+                let (old_pkg, new_pkg) = (&old_packages[old], &new_packages[new]);
+                let file_changes =
+                    diff_files(old_side.files_of(old_pkg), new_side.files_of(new_pkg));
+                let moved = old_pkg.path != new_pkg.path;
+                if old_pkg.version == new_pkg.version && file_changes.is_empty() && !moved {
+                    continue;
+                }
+
+                let location = if moved {
+                    format!("{} -> {}", old_pkg.path, new_pkg.path)
+                } else {
+                    new_pkg.path.clone()
+                };
+                if old_pkg.version == new_pkg.version {
+                    if moved {
+                        counts.moved += 1;
+                    } else {
+                        counts.changed += 1;
+                    }
+                    println!("~ {}@{} ({})", new_pkg.name, new_pkg.version, location);
+                } else {
+                    match compare_versions(&old_pkg.version, &new_pkg.version) {
+                        Ordering::Greater => counts.downgraded += 1,
+                        _ => counts.upgraded += 1,
+                    }
+                    println!(
+                        "~ {} {} -> {} ({})",
+                        new_pkg.name, old_pkg.version, new_pkg.version, location
+                    );
+                }
+
+                counts.files_added += file_changes.added.len();
+                counts.files_removed += file_changes.removed.len();
+                counts.files_modified += file_changes.modified.len();
+                print_file_changes(
+                    &file_changes,
+                    (&old_side, old_pkg),
+                    (&new_side, new_pkg),
+                    options.patch,
+                )?;
+            }
+        }
+    }
+
+    let is_same = counts.added
+        + counts.removed
+        + counts.upgraded
+        + counts.downgraded
+        + counts.changed
+        + counts.moved
+        == 0;
+    let color = if is_same { "\x1b[32m" } else { "\x1b[33m" };
+    print_box(
+        "Diff",
+        &[
+            &format!("Packages added: {}", counts.added),
+            &format!("Packages removed: {}", counts.removed),
+            &format!("Upgraded: {}", counts.upgraded),
+            &format!("Downgraded: {}", counts.downgraded),
+            &format!("Changed in place: {}", counts.changed),
+            &format!("Moved: {}", counts.moved),
+            &format!(
+                "Files: +{} -{} ~{}",
+                counts.files_added, counts.files_removed, counts.files_modified
+            ),
+        ],
+        color,
+    );
+
+    if is_same {
+        eprintln!("Snapshots are identical.");
+    }
+
+    Ok(())
+}
+
+fn change_path<'a>(
+    change: &PackageChange,
+    old: &'a [PackageInfo],
+    new: &'a [PackageInfo],
+) -> &'a str {
+    match *change {
+        PackageChange::Added(j) | PackageChange::Kept { new: j, .. } => &new[j].path,
+        PackageChange::Removed(i) => &old[i].path,
+    }
+}
+
+/// Semver order when both parse, string order otherwise.
+fn compare_versions(old: &str, new: &str) -> Ordering {
+    match (Version::parse(old), Version::parse(new)) {
+        (Ok(old), Ok(new)) => old.cmp(&new),
+        _ => old.cmp(new),
+    }
+}
+
+fn print_file_changes(
+    changes: &FileChanges,
+    (old_side, old_pkg): (&Side, &PackageInfo),
+    (new_side, new_pkg): (&Side, &PackageInfo),
+    patch: bool,
+) -> Result<()> {
+    for path in &changes.added {
+        println!("    A {}", path);
+    }
+    for path in &changes.removed {
+        println!("    D {}", path);
+    }
+
+    for path in &changes.modified {
+        println!("    M {}", path);
+        if !patch {
+            continue;
+        }
+
+        let old_label = format!("a/{}", join_path(&old_pkg.path, path));
+        let new_label = format!("b/{}", join_path(&new_pkg.path, path));
+        let size = old_side
+            .size(old_pkg, path)?
+            .max(new_side.size(new_pkg, path)?);
+        if size > MAX_DIFF_SIZE as u64 {
+            print_file_diff(&FileDiff::TooLarge, &old_label, &new_label);
+            continue;
+        }
+
+        let old_content = old_side.read(old_pkg, path)?;
+        let new_content = new_side.read(new_pkg, path)?;
+        let diff = unified_diff(&old_content, &new_content, &old_label, &new_label);
+        print_file_diff(&diff, &old_label, &new_label);
+    }
+
+    Ok(())
+}
+
+fn join_path(package_path: &str, relative_path: &str) -> String {
+    if package_path.is_empty() {
+        relative_path.to_string()
+    } else {
+        format!("{}/{}", package_path, relative_path)
+    }
+}
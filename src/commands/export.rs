@@ -0,0 +1,114 @@
+use anyhow::{bail, Result};
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::time::Instant;
+
+use crate::commands::pack::print_box;
+use crate::core::exporter::write_archive;
+use crate::core::store::Store;
+use crate::types::{ExportFormat, ExportOptions, ExportSummary, Layout};
+use crate::utils::fs::format_bytes;
+use crate::utils::progress::create_progress_bar;
+
+/// Writes the snapshot to a tar archive, or to stdout for `-o -`.
+pub fn export(options: &ExportOptions) -> Result<()> {
+    let db_path = Path::new(&options.db);
+
+    if !db_path.exists() {
+        bail!("Database not found: {}", db_path.display());
+    }
+
+    let store = Store::open_readonly(db_path.to_str().unwrap_or_default())?;
+    let layout = Layout::from_metadata(store.get_metadata("layout")?.as_deref());
+
+    // Node_modules-rooted snapshots go under node_modules/ so the archive
+    // unpacks into a project directory like the other layouts.
+    let prefix = options.prefix.clone().unwrap_or_else(|| {
+        if layout.is_project_rooted() {
+            String::new()
+        } else {
+            "node_modules".to_string()
+        }
+    });
+    let output = options
+        .output
+        .clone()
+        .unwrap_or_else(|| format!("./node_modules.{}", options.format.extension()));
+    let to_stdout = output == "-";
+
+    eprintln!("Exporting {}", db_path.display());
+    let start = Instant::now();
+
+    let writer: Box<dyn Write> = if to_stdout {
+        Box::new(io::stdout().lock())
+    } else {
+        Box::new(File::create(&output)?)
+    };
+
+    let result = write_compressed(&store, BufWriter::new(writer), &prefix, options.format);
+    if result.is_err() && !to_stdout {
+        let _ = fs::remove_file(&output);
+    }
+    let summary = result?;
+    let elapsed = start.elapsed().as_secs_f64();
+
+    let destination = if to_stdout { "stdout" } else { output.as_str() };
+    print_box(
+        "Export Complete",
+        &[
+            &format!("Output: {}", destination),
+            &format!("Format: {}", options.format.extension()),
+            &format!(
+                "Files: {} ({})",
+                summary.files,
+                format_bytes(summary.total_size)
+            ),
+            &format!("Directories: {}", summary.directories),
+            &format!("Symlinks: {}", summary.symlinks),
+            &format!("Time: {:.1}s", elapsed),
+        ],
+        "\x1b[32m",
+    );
+
+    Ok(())
+}
+
+fn write_compressed<W: Write>(
+    store: &Store,
+    writer: W,
+    prefix: &str,
+    format: ExportFormat,
+) -> Result<ExportSummary> {
+    let total = store.get_total_file_count()?;
+    let pb = create_progress_bar(total as u64);
+    let on_progress = |current: usize, _total: usize, msg: &str| {
+        pb.set_position(current as u64);
+        pb.set_message(msg.to_string());
+    };
+
+    let summary = match format {
+        ExportFormat::Tar => {
+            let (mut writer, summary) = write_archive(store, writer, prefix, Some(&on_progress))?;
+            writer.flush()?;
+            summary
+        }
+        ExportFormat::TarGz => {
+            let encoder = GzEncoder::new(writer, Compression::default());
+            let (encoder, summary) = write_archive(store, encoder, prefix, Some(&on_progress))?;
+            encoder.finish()?.flush()?;
+            summary
+        }
+        ExportFormat::TarZst => {
+            let encoder = zstd::Encoder::new(writer, 0)?;
+            let (encoder, summary) = write_archive(store, encoder, prefix, Some(&on_progress))?;
+            encoder.finish()?.flush()?;
+            summary
+        }
+    };
+    pb.finish_and_clear();
+
+    Ok(summary)
+}
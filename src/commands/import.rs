@@ -0,0 +1,143 @@
+use anyhow::{bail, Result};
+use std::fs;
+use std::path::Path;
+
+use crate::commands::pack::{
+    chrono_now, insert_packages, pack, print_box, remove_db, resolve_db_path, PackedFile,
+};
+use crate::core::importer::import_archive;
+use crate::core::scanner::is_yarn_pnp_structure;
+use crate::core::store::Store;
+use crate::types::{ImportOptions, PackOptions};
+use crate::utils::fs::format_bytes;
+use crate::utils::progress::create_progress_bar;
+
+/// Packs a tarball or an unpacked directory archive into a snapshot DB.
+/// Tarball entries are streamed straight into the DB and grouped into
+/// packages the way `pack` would scan the extracted archive.
+pub fn import(options: &ImportOptions) -> Result<()> {
+    let input = Path::new(&options.input);
+
+    if !input.exists() {
+        bail!("Archive not found: {}", input.display());
+    }
+
+    if input.is_dir() {
+        // A Yarn PnP project is packed from its root, anything else from
+        // its node_modules when it has one.
+        let node_modules = input.join("node_modules");
+        let source = if !is_yarn_pnp_structure(input) && node_modules.is_dir() {
+            node_modules
+        } else {
+            input.to_path_buf()
+        };
+        return pack(&PackOptions {
+            output: options.output.clone(),
+            source: source.to_string_lossy().to_string(),
+            compression_level: options.compression_level,
+            include_lockfile: options.include_lockfile,
+            json: false,
+            workspaces: false,
+            excludes: Vec::new(),
+            prune: false,
+            production: false,
+            from_cache: false,
+            cache: None,
+        });
+    }
+
+    let db_path = resolve_db_path(&options.output);
+    remove_db(&db_path)?;
+    let mut store = Store::open(db_path.to_str().unwrap_or_default())?;
+
+    eprintln!("Importing {}...", input.display());
+    let pb = create_progress_bar(100);
+    let imported = import_archive(
+        input,
+        &mut store,
+        options.compression_level,
+        Some(&|current, total, msg| {
+            let display = msg.char_indices().nth(40).map_or(msg, |(i, _)| &msg[..i]);
+            pb.set_length(total as u64);
+            pb.set_position(current as u64);
+            pb.set_message(display.to_string());
+        }),
+    )?;
+    pb.finish_and_clear();
+
+    let scan_result = &imported.scan_result;
+    eprintln!(
+        "Found {} packages, {} files ({})",
+        scan_result.packages.len(),
+        scan_result.total_files,
+        format_bytes(scan_result.total_size),
+    );
+
+    let anonymous_packages = scan_result.anonymous_packages();
+    for dir in &anonymous_packages {
+        eprintln!(
+            "Warning: {} ({}), stored as an anonymous package",
+            dir.path, dir.reason
+        );
+    }
+
+    store.set_metadata("created_at", &chrono_now())?;
+    store.set_metadata("source_path", &input.canonicalize()?.to_string_lossy())?;
+    store.set_metadata("layout", scan_result.layout.as_str())?;
+    if options.include_lockfile {
+        if let Some(lockfile_hash) = &imported.lockfile_hash {
+            store.set_metadata("lockfile_hash", lockfile_hash)?;
+        }
+    }
+
+    let hashes = &imported.hashes;
+    let files: Vec<PackedFile> = scan_result
+        .packages
+        .iter()
+        .enumerate()
+        .flat_map(|(package_index, pkg)| {
+            pkg.files.iter().map(move |file| PackedFile {
+                package_index,
+                relative_path: file.relative_path.clone(),
+                hash: hashes[&file.absolute_path].clone(),
+                mode: file.mode,
+                mtime: file.mtime,
+            })
+        })
+        .collect();
+    let root_dependencies: Vec<_> = imported
+        .root_dependencies
+        .iter()
+        .map(|dep| (String::new(), dep.clone()))
+        .collect();
+
+    eprintln!("Writing to database...");
+    store
+        .transaction(|tx| insert_packages(tx, &scan_result.packages, &files, &root_dependencies))?;
+
+    let db_size = fs::metadata(&db_path)?.len();
+    let compression_ratio = if scan_result.total_size > 0 {
+        (1.0 - db_size as f64 / scan_result.total_size as f64) * 100.0
+    } else {
+        0.0
+    };
+
+    let mut summary_lines = vec![
+        format!("Output: {}", db_path.display()),
+        format!("Original: {}", format_bytes(scan_result.total_size)),
+        format!("DB size: {}", format_bytes(db_size)),
+        format!("Compression: {:.1}%", compression_ratio),
+        format!("Deduplicated: {}", imported.deduplicated),
+    ];
+    if !anonymous_packages.is_empty() {
+        summary_lines.push(format!(
+            "Without package.json: {}",
+            anonymous_packages.len()
+        ));
+    }
+
+    let line_refs: Vec<&str> = summary_lines.iter().map(|s| s.as_str()).collect();
+    print_box("Import Complete", &line_refs, "\x1b[32m");
+
+    Ok(())
+}
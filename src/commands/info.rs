@@ -0,0 +1,106 @@
+use anyhow::{bail, Result};
+use std::cmp::Reverse;
+use std::path::Path;
+
+use crate::commands::pack::print_box;
+use crate::core::store::Store;
+use crate::utils::fs::format_bytes;
+
+/// How many packages the "Largest Packages" box lists.
+const LARGEST_PACKAGES: usize = 10;
+
+/// Prints a snapshot's metadata and statistics. Only reads the DB.
+pub fn info(db: &str) -> Result<()> {
+    let db_path = Path::new(db);
+
+    if !db_path.exists() {
+        bail!("Database not found: {}", db_path.display());
+    }
+
+    let store = Store::open_readonly(db_path.to_str().unwrap_or_default())?;
+
+    let metadata = store.get_all_metadata()?;
+    let metadata_lines: Vec<String> = metadata
+        .iter()
+        .map(|(key, value)| format!("{}: {}", key, value))
+        .collect();
+    let line_refs: Vec<&str> = metadata_lines.iter().map(|s| s.as_str()).collect();
+    print_box("Metadata", &line_refs, "\x1b[36m");
+
+    let schema_version = store
+        .get_metadata("schema_version")?
+        .unwrap_or_else(|| "1".to_string());
+    let blob_stats = store.get_blob_stats()?;
+    let file_count = store.get_total_file_count()?;
+    let logical_size = store.get_logical_size()?;
+
+    let dedup_saved = logical_size.saturating_sub(blob_stats.total_original_size);
+    let mut stats_lines = vec![
+        format!("Schema version: {}", schema_version),
+        format!("Packages: {}", store.get_package_count()?),
+        format!("Files: {}", file_count),
+        format!("Blobs: {}", blob_stats.total_blobs),
+        format!("Total size: {}", format_bytes(logical_size)),
+        format!(
+            "Deduplicated: {} ({:.1}%)",
+            format_bytes(dedup_saved),
+            percent(dedup_saved, logical_size)
+        ),
+        format!(
+            "Unique size: {}",
+            format_bytes(blob_stats.total_original_size)
+        ),
+        format!(
+            "Compressed size: {}",
+            format_bytes(blob_stats.total_compressed_size)
+        ),
+        format!(
+            "Compression: {:.1}%",
+            100.0
+                - percent(
+                    blob_stats.total_compressed_size,
+                    blob_stats.total_original_size
+                )
+        ),
+    ];
+    for codec in store.get_codec_stats()? {
+        stats_lines.push(format!(
+            "Codec {}: {} blobs ({})",
+            codec.codec,
+            codec.blobs,
+            format_bytes(codec.compressed_size)
+        ));
+    }
+    let line_refs: Vec<&str> = stats_lines.iter().map(|s| s.as_str()).collect();
+    print_box("Statistics", &line_refs, "\x1b[36m");
+
+    let mut packages = store.get_package_stats()?;
+    packages.sort_by_key(|p| Reverse(p.original_size));
+    let largest_lines: Vec<String> = packages
+        .iter()
+        .take(LARGEST_PACKAGES)
+        .map(|p| {
+            format!(
+                "{:>10}  {}@{} ({} files)",
+                format_bytes(p.original_size),
+                p.name,
+                p.version,
+                p.files
+            )
+        })
+        .collect();
+    if !largest_lines.is_empty() {
+        let line_refs: Vec<&str> = largest_lines.iter().map(|s| s.as_str()).collect();
+        print_box("Largest Packages", &line_refs, "\x1b[36m");
+    }
+
+    Ok(())
+}
+
+fn percent(part: u64, total: u64) -> f64 {
+    if total == 0 {
+        0.0
+    } else {
+        part as f64 / total as f64 * 100.0
+    }
+}
@@ -0,0 +1,180 @@
+use anyhow::{bail, Result};
+use serde::Serialize;
+use std::cmp::Reverse;
+use std::path::Path;
+
+use crate::core::filter::PackageFilter;
+use crate::core::store::Store;
+use crate::types::{FileListing, LsOptions, LsSort, PackageStats};
+use crate::utils::fs::format_bytes;
+
+#[derive(Serialize)]
+struct PackageFiles {
+    name: String,
+    version: String,
+    path: String,
+    files: Vec<FileListing>,
+}
+
+pub fn ls(options: &LsOptions) -> Result<()> {
+    let db_path = Path::new(&options.db);
+
+    if !db_path.exists() {
+        bail!("Database not found: {}", db_path.display());
+    }
+
+    let store = Store::open_readonly(db_path.to_str().unwrap_or_default())?;
+
+    match &options.files {
+        Some(spec) => list_files(&store, spec, options),
+        None => list_packages(&store, options),
+    }
+}
+
+fn list_packages(store: &Store, options: &LsOptions) -> Result<()> {
+    let mut packages = store.get_package_stats()?;
+    sort_packages(&mut packages, options.sort);
+
+    if options.json {
+        println!("{}", serde_json::to_string_pretty(&packages)?);
+        return Ok(());
+    }
+
+    let rows: Vec<Vec<String>> = packages
+        .iter()
+        .map(|p| {
+            vec![
+                p.name.clone(),
+                p.version.clone(),
+                p.path.clone(),
+                p.files.to_string(),
+                format_bytes(p.original_size),
+                format_bytes(p.compressed_size),
+            ]
+        })
+        .collect();
+    print_table(
+        &["NAME", "VERSION", "PATH", "FILES", "SIZE", "COMPRESSED"],
+        &rows,
+        &[3, 4, 5],
+    );
+
+    let total_files: usize = packages.iter().map(|p| p.files).sum();
+    let total_size: u64 = packages.iter().map(|p| p.original_size).sum();
+    println!();
+    println!(
+        "{} packages, {} files ({})",
+        packages.len(),
+        total_files,
+        format_bytes(total_size)
+    );
+
+    Ok(())
+}
+
+fn list_files(store: &Store, spec: &str, options: &LsOptions) -> Result<()> {
+    let filter = PackageFilter::new(&[spec.to_string()])?;
+    let packages: Vec<_> = store
+        .get_packages()?
+        .into_iter()
+        .filter(|p| filter.matches(p))
+        .collect();
+    if packages.is_empty() {
+        bail!("No packages match: {}", spec);
+    }
+    if options.sort == LsSort::Files {
+        bail!("--sort files only applies to the package list; use name, path or size with --files");
+    }
+
+    let mut listings = Vec::new();
+    for pkg in packages {
+        let mut files = store.get_file_listing(pkg.id.unwrap_or_default())?;
+        sort_files(&mut files, options.sort);
+        listings.push(PackageFiles {
+            name: pkg.name,
+            version: pkg.version,
+            path: pkg.path,
+            files,
+        });
+    }
+
+    if options.json {
+        println!("{}", serde_json::to_string_pretty(&listings)?);
+        return Ok(());
+    }
+
+    for (n, listing) in listings.iter().enumerate() {
+        if n > 0 {
+            println!();
+        }
+        println!("{}@{} ({})", listing.name, listing.version, listing.path);
+
+        let rows: Vec<Vec<String>> = listing
+            .files
+            .iter()
+            .map(|f| {
+                vec![
+                    format!("{:o}", f.mode & 0o777),
+                    f.size.map_or_else(|| "missing".to_string(), format_bytes),
+                    f.compressed_size
+                        .map_or_else(|| "missing".to_string(), format_bytes),
+                    f.relative_path.clone(),
+                ]
+            })
+            .collect();
+        print_table(&["MODE", "SIZE", "COMPRESSED", "PATH"], &rows, &[1, 2]);
+    }
+
+    Ok(())
+}
+
+fn sort_packages(packages: &mut [PackageStats], sort: LsSort) {
+    match sort {
+        LsSort::Name => packages.sort_by(|a, b| a.name.cmp(&b.name).then(a.path.cmp(&b.path))),
+        LsSort::Path => packages.sort_by(|a, b| a.path.cmp(&b.path)),
+        LsSort::Size => packages.sort_by_key(|p| Reverse(p.original_size)),
+        LsSort::Files => packages.sort_by_key(|p| Reverse(p.files)),
+    }
+}
+
+/// Files have no name/path distinction; `files` is rejected by `list_files`.
+/// Files with a missing blob sort last by size.
+fn sort_files(files: &mut [FileListing], sort: LsSort) {
+    match sort {
+        LsSort::Name | LsSort::Path => files.sort_by(|a, b| a.relative_path.cmp(&b.relative_path)),
+        LsSort::Size | LsSort::Files => files.sort_by_key(|f| Reverse(f.size)),
+    }
+}
+
+/// Prints left-aligned columns, right-aligning those in `numeric`.
+fn print_table(headers: &[&str], rows: &[Vec<String>], numeric: &[usize]) {
+    let mut widths: Vec<usize> = headers.iter().map(|h| h.len()).collect();
+    for row in rows {
+        for (i, cell) in row.iter().enumerate() {
+            widths[i] = widths[i].max(cell.chars().count());
+        }
+    }
+
+    let format_row = |cells: Vec<&str>| {
+        let last = cells.len() - 1;
+        cells
+            .iter()
+            .enumerate()
+            .map(|(i, cell)| {
+                if numeric.contains(&i) {
+                    format!("{:>width$}", cell, width = widths[i])
+                } else if i == last {
+                    cell.to_string()
+                } else {
+                    format!("{:<width$}", cell, width = widths[i])
+                }
+            })
+            .collect::<Vec<_>>()
+            .join("  ")
+    };
+
+    println!("{}", format_row(headers.to_vec()));
+    for row in rows {
+        println!("{}", format_row(row.iter().map(|c| c.as_str()).collect()));
+    }
+}
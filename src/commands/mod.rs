@@ -1,3 +1,11 @@
+pub mod cat;
+pub mod diff;
+pub mod export;
+pub mod import;
+pub mod info;
+pub mod ls;
 pub mod pack;
 pub mod status;
 pub mod unpack;
+pub mod verify;
+pub mod why;
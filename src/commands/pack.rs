@@ -1,52 +1,148 @@
 use anyhow::{bail, Result};
 use rayon::prelude::*;
-use rusqlite::params;
+use rusqlite::{params, Transaction};
 use std::fs;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::sync::atomic::{AtomicUsize, Ordering};
+use tempfile::TempDir;
 
+use crate::core::exclude::{
+    apply_excludes, read_ignore_file, ExcludeFilter, IGNORE_FILE_NAME, PRUNE_PATTERNS,
+};
 use crate::core::hasher::{hash_buffer, hash_string};
-use crate::core::scanner::scan_node_modules;
+use crate::core::npm_cache::{cacache_dir, install_from_cache};
+use crate::core::production::production_packages;
+use crate::core::scanner::{
+    is_yarn_pnp_structure, read_project_dependencies, scan_node_modules, scan_yarn_pnp,
+    ScannedPackage,
+};
 use crate::core::store::Store;
-use crate::types::PackOptions;
+use crate::core::workspace::{find_workspace_dirs, read_workspace_patterns, scan_workspace};
+use crate::types::{Dependency, Layout, PackOptions, PackSummary};
 use crate::utils::compression::compress;
 use crate::utils::fs::format_bytes;
 use crate::utils::progress::create_progress_bar;
 
+/// A file to record, whose blob is stored under `hash`.
+pub struct PackedFile {
+    pub package_index: usize,
+    pub relative_path: String,
+    pub hash: String,
+    pub mode: u32,
+    pub mtime: i64,
+}
+
 struct ProcessedFile {
-    package_index: usize,
-    hash: String,
-    compressed: Option<Vec<u8>>,
+    file: PackedFile,
+    compressed: Vec<u8>,
     original_size: u64,
-    mode: u32,
-    mtime: i64,
-    relative_path: String,
 }
 
 pub fn pack(options: &PackOptions) -> Result<()> {
-    let node_modules_path = Path::new(&options.source).canonicalize()?;
-    let db_path = fs::canonicalize(Path::new(&options.output).parent().unwrap_or(Path::new(".")))
-        .unwrap_or_default()
-        .join(
-            Path::new(&options.output)
-                .file_name()
-                .unwrap_or_default(),
-        );
-
-    if !node_modules_path.exists() {
-        bail!("node_modules not found: {}", node_modules_path.display());
+    if options.from_cache {
+        return pack_from_cache(options);
     }
 
-    eprintln!("Scanning {}...", node_modules_path.display());
+    let source_path = Path::new(&options.source);
+    let db_path = resolve_db_path(&options.output);
+
+    // A Yarn PnP project has no node_modules; accept either the project
+    // root or the (missing) node_modules path inside it.
+    let pnp_root = if source_path.is_dir() {
+        Some(source_path).filter(|p| is_yarn_pnp_structure(p))
+    } else {
+        Some(parent_dir(source_path)).filter(|p| is_yarn_pnp_structure(p))
+    };
 
     let scan_pb = create_progress_bar(100);
-    let scan_result = scan_node_modules(&node_modules_path, Some(&|current, total, msg| {
+    let on_scan_progress = |current: usize, total: usize, msg: &str| {
         scan_pb.set_length(total as u64);
         scan_pb.set_position(current as u64);
         scan_pb.set_message(msg.to_string());
-    }))?;
+    };
+
+    let mut locations: Option<Vec<String>> = None;
+
+    let (node_modules_path, mut scan_result) = if options.workspaces {
+        let project_path = if source_path.file_name().is_some_and(|n| n == "node_modules") {
+            parent_dir(source_path)
+        } else {
+            source_path
+        };
+        let project_path = project_path.canonicalize()?;
+        eprintln!("Scanning workspaces in {}...", project_path.display());
+        let (scan_result, found) = scan_workspace(&project_path, Some(&on_scan_progress))?;
+        if found.is_empty() {
+            bail!("No node_modules found in {}", project_path.display());
+        }
+        for location in &found {
+            eprintln!("  {}", location);
+        }
+        locations = Some(found);
+        (project_path, scan_result)
+    } else if let Some(project_path) = pnp_root {
+        let project_path = project_path.canonicalize()?;
+        eprintln!("Scanning {} (Yarn PnP)...", project_path.display());
+        let scan_result = scan_yarn_pnp(&project_path, Some(&on_scan_progress))?;
+        (project_path, scan_result)
+    } else {
+        if !source_path.exists() {
+            bail!("node_modules not found: {}", source_path.display());
+        }
+        let node_modules_path = source_path.canonicalize()?;
+        eprintln!("Scanning {}...", node_modules_path.display());
+        let scan_result = scan_node_modules(&node_modules_path, Some(&on_scan_progress))?;
+        (node_modules_path, scan_result)
+    };
     scan_pb.finish_and_clear();
 
+    let project_path = if scan_result.layout.is_project_rooted() {
+        node_modules_path.clone()
+    } else {
+        parent_dir(&node_modules_path).to_path_buf()
+    };
+
+    let mut dev_packages = 0;
+    if options.production {
+        let keep = production_packages(
+            &project_path,
+            &node_modules_path,
+            scan_result.layout,
+            &scan_result.packages,
+        )?;
+        dev_packages = scan_result.packages.len() - keep.len();
+
+        scan_result.packages = std::mem::take(&mut scan_result.packages)
+            .into_iter()
+            .enumerate()
+            .filter(|(index, _)| keep.contains(index))
+            .map(|(_, pkg)| pkg)
+            .collect();
+        scan_result.total_files = scan_result.packages.iter().map(|p| p.files.len()).sum();
+        scan_result.total_size = scan_result
+            .packages
+            .iter()
+            .flat_map(|p| p.files.iter())
+            .map(|f| f.size)
+            .sum();
+        eprintln!("Production: skipping {} dev-only packages", dev_packages);
+    }
+    let mut exclude_patterns = read_ignore_file(&project_path.join(IGNORE_FILE_NAME))?;
+    exclude_patterns.extend(options.excludes.iter().cloned());
+    if options.prune {
+        exclude_patterns.extend(PRUNE_PATTERNS.iter().map(|p| p.to_string()));
+    }
+
+    let exclude = ExcludeFilter::new(&exclude_patterns)?;
+    let (excluded_files, excluded_size) = if exclude.is_empty() {
+        (0, 0)
+    } else {
+        let excluded = apply_excludes(&mut scan_result.packages, &exclude);
+        scan_result.total_files -= excluded.0;
+        scan_result.total_size -= excluded.1;
+        excluded
+    };
+
     eprintln!(
         "Found {} packages, {} files ({})",
         scan_result.packages.len(),
@@ -54,31 +150,54 @@ pub fn pack(options: &PackOptions) -> Result<()> {
         format_bytes(scan_result.total_size),
     );
 
-    if db_path.exists() {
-        fs::remove_file(&db_path)?;
-        let wal = db_path.with_extension("db-wal");
-        let shm = db_path.with_extension("db-shm");
-        if wal.exists() {
-            fs::remove_file(&wal)?;
-        }
-        if shm.exists() {
-            fs::remove_file(&shm)?;
-        }
+    let anonymous_packages = scan_result.anonymous_packages();
+    for dir in &anonymous_packages {
+        eprintln!(
+            "Warning: {} ({}), stored as an anonymous package",
+            dir.path, dir.reason
+        );
     }
 
+    remove_db(&db_path)?;
+
     let mut store = Store::open(db_path.to_str().unwrap_or_default())?;
 
     store.set_metadata("created_at", &chrono_now())?;
     store.set_metadata("source_path", &node_modules_path.to_string_lossy())?;
+    store.set_metadata("layout", scan_result.layout.as_str())?;
+    if let Some(locations) = &locations {
+        store.set_metadata("locations", &serde_json::to_string(locations)?)?;
+    }
+    if !exclude.is_empty() {
+        store.set_metadata("exclude", &serde_json::to_string(&exclude_patterns)?)?;
+    }
 
     if options.include_lockfile {
-        let lockfile_path = node_modules_path.join("..").join("package-lock.json");
+        let lockfile_path = match scan_result.layout {
+            Layout::YarnPnp => node_modules_path.join("yarn.lock"),
+            Layout::Workspace => node_modules_path.join("package-lock.json"),
+            Layout::NodeModules => node_modules_path.join("..").join("package-lock.json"),
+        };
         if lockfile_path.exists() {
             let content = fs::read_to_string(&lockfile_path)?;
             store.set_metadata("lockfile_hash", &hash_string(&content))?;
         }
     }
 
+    let mut importers = vec![String::new()];
+    if scan_result.layout == Layout::Workspace {
+        let patterns = read_workspace_patterns(&project_path)?;
+        importers.extend(find_workspace_dirs(&project_path, &patterns)?);
+    }
+    let root_dependencies: Vec<(String, Dependency)> = importers
+        .into_iter()
+        .flat_map(|importer| {
+            read_project_dependencies(&project_path.join(&importer).join("package.json"))
+                .into_iter()
+                .map(move |dep| (importer.clone(), dep))
+        })
+        .collect();
+
     eprintln!("Packing files...");
 
     let pack_pb = create_progress_bar(scan_result.total_files as u64);
@@ -105,22 +224,24 @@ pub fn pack(options: &PackOptions) -> Result<()> {
             let compressed = compress(&content, compression_level);
 
             let count = processed_count.fetch_add(1, Ordering::Relaxed) + 1;
-            let display = if file.relative_path.len() > 40 {
-                &file.relative_path[..40]
-            } else {
-                &file.relative_path
-            };
+            let display = &file.relative_path;
+            let display = display
+                .char_indices()
+                .nth(40)
+                .map_or(display.as_str(), |(i, _)| &display[..i]);
             pack_pb.set_position(count as u64);
             pack_pb.set_message(display.to_string());
 
             Some(ProcessedFile {
-                package_index: *pi,
-                hash,
-                compressed: Some(compressed),
+                file: PackedFile {
+                    package_index: *pi,
+                    relative_path: file.relative_path.clone(),
+                    hash,
+                    mode: file.mode,
+                    mtime: file.mtime,
+                },
+                compressed,
                 original_size: content.len() as u64,
-                mode: file.mode,
-                mtime: file.mtime,
-                relative_path: file.relative_path.clone(),
             })
         })
         .collect();
@@ -133,63 +254,30 @@ pub fn pack(options: &PackOptions) -> Result<()> {
     let mut seen_hashes = std::collections::HashSet::new();
 
     store.transaction(|tx| {
-        let mut insert_pkg_stmt = tx.prepare_cached(
-            "INSERT INTO packages (name, version, path) VALUES (?1, ?2, ?3)
-             ON CONFLICT(name, version, path) DO UPDATE SET name = name
-             RETURNING id",
-        )?;
         let mut insert_blob_stmt = tx.prepare_cached(
             "INSERT OR IGNORE INTO blobs (hash, content, original_size, compressed_size)
              VALUES (?1, ?2, ?3, ?4)",
         )?;
-        let mut insert_file_stmt = tx.prepare_cached(
-            "INSERT INTO files (package_id, relative_path, blob_hash, mode, mtime)
-             VALUES (?1, ?2, ?3, ?4, ?5)
-             ON CONFLICT(package_id, relative_path) DO UPDATE SET
-               blob_hash = excluded.blob_hash,
-               mode = excluded.mode,
-               mtime = excluded.mtime",
-        )?;
-
-        let mut package_ids: Vec<Option<i64>> = vec![None; scan_result.packages.len()];
 
         for pf in &processed {
-            let pkg_id = if let Some(id) = package_ids[pf.package_index] {
-                id
-            } else {
-                let pkg = &scan_result.packages[pf.package_index];
-                let id: i64 = insert_pkg_stmt.query_row(
-                    params![pkg.info.name, pkg.info.version, pkg.info.path],
-                    |row| row.get(0),
-                )?;
-                package_ids[pf.package_index] = Some(id);
-                id
-            };
-
-            if !seen_hashes.contains(&pf.hash) {
-                if let Some(ref compressed) = pf.compressed {
-                    insert_blob_stmt.execute(params![
-                        pf.hash,
-                        compressed,
-                        pf.original_size,
-                        compressed.len() as u64
-                    ])?;
-                    seen_hashes.insert(pf.hash.clone());
-                }
+            if seen_hashes.insert(pf.file.hash.as_str()) {
+                insert_blob_stmt.execute(params![
+                    pf.file.hash,
+                    pf.compressed,
+                    pf.original_size,
+                    pf.compressed.len() as u64
+                ])?;
             } else {
                 deduplicated_count += 1;
             }
-
-            insert_file_stmt.execute(params![
-                pkg_id,
-                pf.relative_path,
-                pf.hash,
-                pf.mode,
-                pf.mtime
-            ])?;
         }
 
-        Ok(())
+        insert_packages(
+            tx,
+            &scan_result.packages,
+            processed.iter().map(|pf| &pf.file),
+            &root_dependencies,
+        )
     })?;
 
     let db_size = fs::metadata(&db_path)?.len();
@@ -199,22 +287,243 @@ pub fn pack(options: &PackOptions) -> Result<()> {
         0.0
     };
 
-    print_box(
-        "Pack Complete",
-        &[
-            &format!("Output: {}", db_path.display()),
-            &format!("Original: {}", format_bytes(scan_result.total_size)),
-            &format!("DB size: {}", format_bytes(db_size)),
-            &format!("Compression: {:.1}%", compression_ratio),
-            &format!("Deduplicated: {}", deduplicated_count),
-        ],
-        "\x1b[32m",
+    let mut summary_lines = vec![
+        format!("Output: {}", db_path.display()),
+        format!("Original: {}", format_bytes(scan_result.total_size)),
+        format!("DB size: {}", format_bytes(db_size)),
+        format!("Compression: {:.1}%", compression_ratio),
+        format!("Deduplicated: {}", deduplicated_count),
+    ];
+    if options.production {
+        summary_lines.push(format!("Dev packages skipped: {}", dev_packages));
+    }
+    if excluded_files > 0 {
+        summary_lines.push(format!(
+            "Excluded: {} files ({})",
+            excluded_files,
+            format_bytes(excluded_size)
+        ));
+    }
+    if !anonymous_packages.is_empty() {
+        summary_lines.push(format!(
+            "Without package.json: {}",
+            anonymous_packages.len()
+        ));
+    }
+
+    let line_refs: Vec<&str> = summary_lines.iter().map(|s| s.as_str()).collect();
+    print_box("Pack Complete", &line_refs, "\x1b[32m");
+
+    if options.json {
+        let summary = PackSummary {
+            output: db_path.to_string_lossy().to_string(),
+            packages: scan_result.packages.len(),
+            files: scan_result.total_files,
+            original_size: scan_result.total_size,
+            db_size,
+            deduplicated: deduplicated_count,
+            excluded_files,
+            excluded_size,
+            dev_packages,
+            anonymous_packages,
+        };
+        println!("{}", serde_json::to_string_pretty(&summary)?);
+    }
+
+    Ok(())
+}
+
+/// Packs without an installed node_modules: the lockfile's tarballs are
+/// laid out from the npm cache in a temporary project, which is then packed
+/// as usual.
+fn pack_from_cache(options: &PackOptions) -> Result<()> {
+    let source_path = Path::new(&options.source);
+    let project_path = if source_path.file_name().is_some_and(|n| n == "node_modules") {
+        parent_dir(source_path)
+    } else {
+        source_path
+    };
+
+    let cacache = cacache_dir(options.cache.as_deref());
+    if !cacache.is_dir() {
+        bail!("npm cache not found: {}", cacache.display());
+    }
+
+    let staging = TempDir::with_prefix("mohyung-")?;
+    fs::create_dir_all(staging.path().join("node_modules"))?;
+
+    eprintln!(
+        "Reading {} from {}...",
+        project_path.join("package-lock.json").display(),
+        cacache.display()
     );
+    let pb = create_progress_bar(100);
+    let install = install_from_cache(
+        project_path,
+        &cacache,
+        staging.path(),
+        Some(&|current, total, msg| {
+            pb.set_length(total as u64);
+            pb.set_position(current as u64);
+            pb.set_message(msg.to_string());
+        }),
+    )?;
+    pb.finish_and_clear();
+
+    eprintln!("Extracted {} packages from the npm cache", install.packages);
+    for path in &install.skipped_optional {
+        eprintln!("Skipping optional {} (not in the npm cache)", path);
+    }
+    for path in &install.unverified {
+        eprintln!(
+            "Warning: {} has a sha1 integrity, its tarball is not verified",
+            path
+        );
+    }
+
+    let source = if options.workspaces {
+        staging.path().to_path_buf()
+    } else {
+        staging.path().join("node_modules")
+    };
+    pack(&PackOptions {
+        source: source.to_string_lossy().to_string(),
+        from_cache: false,
+        ..options.clone()
+    })?;
+
+    // Point at the lockfile rather than the temporary directory.
+    let store = Store::open(&options.output)?;
+    let lockfile_path = project_path.canonicalize()?.join("package-lock.json");
+    store.set_metadata("source_path", &lockfile_path.to_string_lossy())?;
+
+    Ok(())
+}
+
+/// Records packages with their files, directories, dependencies and bins,
+/// plus the project's own dependencies. Blobs must already be stored.
+pub fn insert_packages<'a>(
+    tx: &Transaction,
+    packages: &[ScannedPackage],
+    files: impl IntoIterator<Item = &'a PackedFile>,
+    root_dependencies: &[(String, Dependency)],
+) -> Result<()> {
+    let mut insert_pkg_stmt = tx.prepare_cached(
+        "INSERT INTO packages (name, version, path) VALUES (?1, ?2, ?3)
+         ON CONFLICT(name, version, path) DO UPDATE SET name = name
+         RETURNING id",
+    )?;
+    let mut insert_file_stmt = tx.prepare_cached(
+        "INSERT INTO files (package_id, relative_path, blob_hash, mode, mtime)
+         VALUES (?1, ?2, ?3, ?4, ?5)
+         ON CONFLICT(package_id, relative_path) DO UPDATE SET
+           blob_hash = excluded.blob_hash,
+           mode = excluded.mode,
+           mtime = excluded.mtime",
+    )?;
+    let mut insert_dir_stmt = tx.prepare_cached(
+        "INSERT OR REPLACE INTO directories (package_id, relative_path, mode)
+         VALUES (?1, ?2, ?3)",
+    )?;
+    let mut insert_dep_stmt = tx.prepare_cached(
+        "INSERT OR REPLACE INTO dependencies (package_id, name, spec, kind)
+         VALUES (?1, ?2, ?3, ?4)",
+    )?;
+    let mut insert_bin_stmt = tx.prepare_cached(
+        "INSERT OR REPLACE INTO bins (package_id, name, path) VALUES (?1, ?2, ?3)",
+    )?;
+
+    let mut insert_root_dep_stmt = tx.prepare_cached(
+        "INSERT OR REPLACE INTO root_dependencies (importer, name, spec, kind)
+         VALUES (?1, ?2, ?3, ?4)",
+    )?;
+
+    let mut package_ids: Vec<Option<i64>> = vec![None; packages.len()];
+
+    for file in files {
+        let pkg_id = if let Some(id) = package_ids[file.package_index] {
+            id
+        } else {
+            let pkg = &packages[file.package_index];
+            let id: i64 = insert_pkg_stmt.query_row(
+                params![pkg.info.name, pkg.info.version, pkg.info.path],
+                |row| row.get(0),
+            )?;
+            package_ids[file.package_index] = Some(id);
+            id
+        };
+
+        insert_file_stmt.execute(params![
+            pkg_id,
+            file.relative_path,
+            file.hash,
+            file.mode,
+            file.mtime
+        ])?;
+    }
+
+    for (index, pkg) in packages.iter().enumerate() {
+        if pkg.directories.is_empty() && pkg.dependencies.is_empty() && pkg.bins.is_empty() {
+            continue;
+        }
+
+        let pkg_id = match package_ids[index] {
+            Some(id) => id,
+            None => insert_pkg_stmt.query_row(
+                params![pkg.info.name, pkg.info.version, pkg.info.path],
+                |row| row.get(0),
+            )?,
+        };
+
+        for dir in &pkg.directories {
+            insert_dir_stmt.execute(params![pkg_id, dir.relative_path, dir.mode])?;
+        }
+        for dep in &pkg.dependencies {
+            insert_dep_stmt.execute(params![pkg_id, dep.name, dep.spec, dep.kind.as_str()])?;
+        }
+        for bin in &pkg.bins {
+            insert_bin_stmt.execute(params![pkg_id, bin.name, bin.path])?;
+        }
+    }
+
+    for (importer, dep) in root_dependencies {
+        insert_root_dep_stmt.execute(params![importer, dep.name, dep.spec, dep.kind.as_str()])?;
+    }
 
     Ok(())
 }
 
-fn chrono_now() -> String {
+/// `output` with its directory made absolute.
+pub fn resolve_db_path(output: &str) -> PathBuf {
+    fs::canonicalize(Path::new(output).parent().unwrap_or(Path::new(".")))
+        .unwrap_or_default()
+        .join(Path::new(output).file_name().unwrap_or_default())
+}
+
+/// Removes a previous DB at `db_path` along with its WAL side files.
+pub fn remove_db(db_path: &Path) -> Result<()> {
+    if db_path.exists() {
+        fs::remove_file(db_path)?;
+        let wal = db_path.with_extension("db-wal");
+        let shm = db_path.with_extension("db-shm");
+        if wal.exists() {
+            fs::remove_file(&wal)?;
+        }
+        if shm.exists() {
+            fs::remove_file(&shm)?;
+        }
+    }
+    Ok(())
+}
+
+fn parent_dir(path: &Path) -> &Path {
+    match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    }
+}
+
+pub fn chrono_now() -> String {
     let now = std::time::SystemTime::now()
         .duration_since(std::time::UNIX_EPOCH)
         .unwrap_or_default()
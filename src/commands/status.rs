@@ -1,23 +1,35 @@
 use anyhow::{bail, Result};
-use rayon::prelude::*;
-use std::collections::HashSet;
+use std::collections::HashMap;
 use std::path::Path;
-use std::sync::Mutex;
 
 use crate::commands::pack::print_box;
-use crate::core::hasher::hash_buffer;
+use crate::core::comparer::{compare_files, drop_excluded, package_scopes, summarize_packages};
+use crate::core::exclude::ExcludeFilter;
+use crate::core::scanner::ROOT_PACKAGE_NAME;
 use crate::core::store::Store;
-use crate::types::StatusResult;
+use crate::types::{
+    FileRecordWithPath, Layout, PackageState, PackageStatus, StatusOptions, StatusResult,
+};
+use crate::utils::compression::decompress;
+use crate::utils::diff::{print_file_diff, unified_diff, FileDiff, MAX_DIFF_SIZE};
 use crate::utils::progress::create_progress_bar;
 
-pub fn status(db: &str, node_modules: &str) -> Result<StatusResult> {
-    let db_path = Path::new(db);
-    let node_modules_path = Path::new(node_modules);
+pub fn status(options: &StatusOptions) -> Result<StatusResult> {
+    let db_path = Path::new(&options.db);
 
     if !db_path.exists() {
         bail!("Database not found: {}", db_path.display());
     }
 
+    let store = Store::open_readonly(db_path.to_str().unwrap_or_default())?;
+    let layout = Layout::from_metadata(store.get_metadata("layout")?.as_deref());
+    let node_modules_path = Path::new(
+        options
+            .node_modules
+            .as_deref()
+            .unwrap_or(layout.default_path()),
+    );
+
     if !node_modules_path.exists() {
         eprintln!("node_modules not found: {}", node_modules_path.display());
         eprintln!("Run \"mohyung unpack\" to restore from database.");
@@ -28,58 +40,82 @@ pub fn status(db: &str, node_modules: &str) -> Result<StatusResult> {
     eprintln!("DB: {}", db_path.display());
     eprintln!("node_modules: {}", node_modules_path.display());
 
-    let store = Store::open(db_path.to_str().unwrap_or_default())?;
     let files = store.get_all_files()?;
+    let packages = store.get_packages()?;
     let total = files.len();
 
-    let pb = create_progress_bar(total as u64);
+    // Only look for untracked files inside the snapshot's own directories
+    // when it is rooted at the project, not at node_modules.
+    let scopes = if layout.is_project_rooted() {
+        Some(package_scopes(&packages))
+    } else {
+        None
+    };
 
-    let result = Mutex::new(StatusResult::default());
-    let db_paths = Mutex::new(HashSet::new());
+    let roots = layout.node_modules_roots(store.get_metadata("locations")?.as_deref());
 
-    files.par_iter().enumerate().for_each(|(index, file)| {
-        let relative_path = format!("{}/{}", file.package_path, file.record.relative_path);
-        let full_path = node_modules_path.join(&relative_path);
+    let pb = create_progress_bar(total as u64);
+    let mut result = compare_files(
+        &files,
+        node_modules_path,
+        scopes.as_deref(),
+        &roots,
+        Some(&|current, _total, msg| {
+            pb.set_position(current as u64);
+            pb.set_message(msg.to_string());
+        }),
+    );
+    pb.finish_and_clear();
 
-        db_paths.lock().unwrap().insert(relative_path.clone());
+    // Files the snapshot left out on purpose aren't untracked.
+    let exclude = ExcludeFilter::from_metadata(store.get_metadata("exclude")?.as_deref())?;
+    drop_excluded(&mut result, &packages, &exclude);
 
-        pb.set_position((index + 1) as u64);
-        if file.record.relative_path.len() > 40 {
-            pb.set_message(file.record.relative_path[..40].to_string());
-        } else {
-            pb.set_message(file.record.relative_path.clone());
+    let package_statuses = if options.packages {
+        let statuses = summarize_packages(&files, &packages, &result, node_modules_path, &roots)?;
+        for status in &statuses {
+            println!("{}", format_package_status(status));
         }
+        Some(statuses)
+    } else {
+        None
+    };
 
-        if !full_path.exists() {
-            result.lock().unwrap().only_in_db.push(relative_path);
-            return;
-        }
-
-        match std::fs::read(&full_path) {
-            Ok(content) => {
-                let fs_hash = hash_buffer(&content);
-                if fs_hash != file.record.blob_hash {
-                    result.lock().unwrap().modified.push(relative_path);
-                } else {
-                    result.lock().unwrap().unchanged += 1;
-                }
-            }
-            Err(_) => {
-                result.lock().unwrap().modified.push(relative_path);
-            }
-        }
-    });
-
-    pb.finish_and_clear();
-
-    let result = result.into_inner().unwrap();
+    if options.diff {
+        print_diffs(&store, &files, &result.modified, node_modules_path)?;
+    }
 
     let mut summary_lines = vec![
         format!("Unchanged: {}", result.unchanged),
         format!("Modified: {}", result.modified.len()),
         format!("Only in DB: {}", result.only_in_db.len()),
+        format!("Only in node_modules: {}", result.only_in_fs.len()),
     ];
 
+    if let Some(statuses) = &package_statuses {
+        let count = |state: PackageState| statuses.iter().filter(|s| s.state == state).count();
+        summary_lines.push(String::new());
+        summary_lines.push(format!(
+            "Packages changed: {}",
+            count(PackageState::Changed)
+        ));
+        summary_lines.push(format!(
+            "Packages missing: {}",
+            count(PackageState::Missing)
+        ));
+        summary_lines.push(format!(
+            "Packages untracked: {}",
+            count(PackageState::Untracked)
+        ));
+        summary_lines.push(format!(
+            "Version mismatches: {}",
+            statuses
+                .iter()
+                .filter(|s| s.installed_version.is_some())
+                .count()
+        ));
+    }
+
     if !result.modified.is_empty() && result.modified.len() <= 10 {
         summary_lines.push(String::new());
         summary_lines.push("Modified files:".to_string());
@@ -96,12 +132,23 @@ pub fn status(db: &str, node_modules: &str) -> Result<StatusResult> {
         }
     }
 
-    if result.modified.len() > 10 || result.only_in_db.len() > 10 {
+    if !result.only_in_fs.is_empty() && result.only_in_fs.len() <= 10 {
+        summary_lines.push(String::new());
+        summary_lines.push("Only in node_modules (untracked):".to_string());
+        for f in &result.only_in_fs {
+            summary_lines.push(format!("  ? {}", f));
+        }
+    }
+
+    if result.modified.len() > 10 || result.only_in_db.len() > 10 || result.only_in_fs.len() > 10 {
         summary_lines.push(String::new());
         summary_lines.push("(Use verbose mode for full list)".to_string());
     }
 
-    let is_clean = result.modified.is_empty() && result.only_in_db.is_empty();
+    let is_clean = result.modified.is_empty()
+        && result.only_in_db.is_empty()
+        && result.only_in_fs.is_empty()
+        && package_statuses.as_ref().is_none_or(|s| s.is_empty());
     let color = if is_clean { "\x1b[32m" } else { "\x1b[33m" };
 
     let line_refs: Vec<&str> = summary_lines.iter().map(|s| s.as_str()).collect();
@@ -113,3 +160,83 @@ pub fn status(db: &str, node_modules: &str) -> Result<StatusResult> {
 
     Ok(result)
 }
+
+/// Diffs each modified file's stored content (`a/`) against the file on
+/// disk (`b/`). Sizes are checked first, so large files are neither
+/// decompressed nor read.
+fn print_diffs(
+    store: &Store,
+    files: &[FileRecordWithPath],
+    modified: &[String],
+    node_modules_path: &Path,
+) -> Result<()> {
+    let hashes: HashMap<String, &str> = files
+        .iter()
+        .map(|f| (f.node_modules_path(), f.record.blob_hash.as_str()))
+        .collect();
+
+    for path in modified {
+        let Some(hash) = hashes.get(path) else {
+            continue;
+        };
+        let old_label = format!("a/{}", path);
+        let new_label = format!("b/{}", path);
+        let full_path = node_modules_path.join(path);
+        let Ok(metadata) = std::fs::metadata(&full_path) else {
+            println!("Cannot read {}", new_label);
+            continue;
+        };
+
+        let stored_size = store.get_blob_size(hash)?.unwrap_or_default();
+        if stored_size.max(metadata.len()) > MAX_DIFF_SIZE as u64 {
+            print_file_diff(&FileDiff::TooLarge, &old_label, &new_label);
+            continue;
+        }
+
+        let stored = match store.get_blob(hash)? {
+            Some(compressed) => decompress(&compressed)?,
+            None => bail!("Blob missing for {}: {}", path, hash),
+        };
+        let Ok(on_disk) = std::fs::read(&full_path) else {
+            println!("Cannot read {}", new_label);
+            continue;
+        };
+
+        let diff = unified_diff(&stored, &on_disk, &old_label, &new_label);
+        print_file_diff(&diff, &old_label, &new_label);
+    }
+
+    Ok(())
+}
+
+/// `lodash@4.17.21: installed 4.17.20, 2 modified`, `left-pad@1.3.0: missing`.
+fn format_package_status(status: &PackageStatus) -> String {
+    let label = if status.name == ROOT_PACKAGE_NAME {
+        status.name.clone()
+    } else {
+        format!("{}@{}", status.name, status.version)
+    };
+
+    let details = match status.state {
+        PackageState::Missing => "missing".to_string(),
+        PackageState::Untracked => "untracked".to_string(),
+        PackageState::Changed => {
+            let mut parts = Vec::new();
+            if let Some(installed) = &status.installed_version {
+                parts.push(format!("installed {}", installed));
+            }
+            for (count, what) in [
+                (status.modified, "modified"),
+                (status.deleted, "deleted"),
+                (status.untracked, "untracked"),
+            ] {
+                if count > 0 {
+                    parts.push(format!("{} {}", count, what));
+                }
+            }
+            parts.join(", ")
+        }
+    };
+
+    format!("{}: {}", label, details)
+}
@@ -1,43 +1,106 @@
 use anyhow::{bail, Result};
+use std::collections::HashSet;
 use std::fs;
 use std::path::Path;
 use std::time::Instant;
 
 use crate::commands::pack::print_box;
-use crate::core::extractor::extract_files_parallel;
+use crate::core::comparer::{compare_files, drop_excluded, package_scopes};
+use crate::core::exclude::ExcludeFilter;
+use crate::core::extractor::{extract_files_parallel, restore_directories};
+use crate::core::filter::PackageFilter;
+use crate::core::journal::Journal;
+use crate::core::scanner::PNP_CACHE_DIR;
 use crate::core::store::Store;
-use crate::types::UnpackOptions;
+use crate::types::{
+    DirectoryRecord, ExtractOptions, FileRecordWithPath, Layout, PackageInfo, UnpackOptions,
+};
 use crate::utils::fs::format_bytes;
 use crate::utils::progress::create_progress_bar;
 
 pub fn unpack(options: &UnpackOptions) -> Result<()> {
     let db_path = Path::new(&options.input);
-    let output_path = Path::new(&options.output);
 
     if !db_path.exists() {
         bail!("Database not found: {}", db_path.display());
     }
 
-    if output_path.exists() {
-        if !options.force {
-            bail!(
-                "Output directory already exists: {}. Use --force to overwrite.",
-                output_path.display()
-            );
-        }
-
-        eprintln!("Removing existing {}...", output_path.display());
-        fs::remove_dir_all(output_path)?;
-    }
+    let filter = PackageFilter::new(&options.packages)?;
 
     eprintln!("Opening {}", db_path.display());
-    let store = Store::open(db_path.to_str().unwrap_or_default())?;
+    let store = Store::open_readonly(db_path.to_str().unwrap_or_default())?;
+
+    let layout = Layout::from_metadata(store.get_metadata("layout")?.as_deref());
+    let output = options
+        .output
+        .clone()
+        .unwrap_or_else(|| layout.default_path().to_string());
+    let output_path = Path::new(&output);
 
     let created_at = store
         .get_metadata("created_at")?
         .unwrap_or_else(|| "unknown".to_string());
     let total_file_count = store.get_total_file_count()?;
     let blob_stats = store.get_blob_stats()?;
+    let all_packages = store.get_packages()?;
+
+    // Project-rooted snapshots restore into a directory that holds other
+    // things too, so only the snapshot's own package paths count.
+    let occupied = if layout.is_project_rooted() {
+        package_scopes(&all_packages)
+            .iter()
+            .any(|p| output_path.join(p).exists())
+    } else {
+        output_path.exists()
+    };
+
+    let sync = options.sync && occupied;
+    let snapshot_id = format!(
+        "{} {} {}",
+        created_at, total_file_count, blob_stats.total_blobs
+    );
+    let completed = if sync {
+        None
+    } else {
+        Journal::load(output_path, &snapshot_id)?
+    };
+
+    if occupied && !options.force && !sync && completed.is_none() {
+        if layout.is_project_rooted() {
+            bail!(
+                "Snapshot contents already exist in {}. Use --force to overwrite.",
+                output_path.display()
+            );
+        }
+        bail!(
+            "Output directory already exists: {}. Use --force to overwrite.",
+            output_path.display()
+        );
+    }
+
+    let selected: Option<Vec<PackageInfo>> = if filter.is_empty() {
+        None
+    } else {
+        let packages: Vec<PackageInfo> = all_packages
+            .iter()
+            .filter(|pkg| filter.matches(pkg))
+            .cloned()
+            .collect();
+        if packages.is_empty() {
+            bail!("No packages match: {}", options.packages.join(", "));
+        }
+        Some(packages)
+    };
+
+    if occupied && !sync && completed.is_none() {
+        if selected.is_none() && !layout.is_project_rooted() {
+            eprintln!("Removing existing {}...", output_path.display());
+            fs::remove_dir_all(output_path)?;
+        } else {
+            let packages = selected.as_deref().unwrap_or(&all_packages);
+            remove_package_dirs(&store, output_path, packages, &all_packages)?;
+        }
+    }
 
     print_box(
         "Database Info",
@@ -53,22 +116,241 @@ pub fn unpack(options: &UnpackOptions) -> Result<()> {
         "\x1b[36m",
     );
 
-    eprintln!("Extracting to {}", output_path.display());
-    let pb = create_progress_bar(total_file_count as u64);
+    let selected_ids: Option<Vec<i64>> = selected
+        .as_ref()
+        .map(|packages| packages.iter().filter_map(|p| p.id).collect());
+    let files = match &selected_ids {
+        None => store.get_all_files()?,
+        Some(ids) => {
+            eprintln!("Selected {} package(s)", ids.len());
+            store.get_files_for_packages(ids)?
+        }
+    };
+    let directories = store.get_directories(selected_ids.as_deref())?;
+
+    let extract_options = ExtractOptions {
+        link_mode: options.link_mode,
+        restore_mtime: options.restore_mtime,
+        fixed_mtime: options.mtime_epoch.map(|secs| secs * 1000),
+    };
+
+    if sync {
+        let scopes: Option<Vec<String>> = match &selected {
+            Some(packages) => Some(package_scopes(packages)),
+            None if layout.is_project_rooted() => Some(package_scopes(&all_packages)),
+            None => None,
+        };
+        let roots = layout.node_modules_roots(store.get_metadata("locations")?.as_deref());
+        return sync_files(
+            &store,
+            &files,
+            &directories,
+            output_path,
+            &extract_options,
+            scopes.as_deref(),
+            &roots,
+        );
+    }
 
     let start = Instant::now();
-    let (total_files, total_size) = extract_files_parallel(&store, output_path, Some(&|current, total, msg| {
-        pb.set_length(total as u64);
-        pb.set_position(current as u64);
-        pb.set_message(msg.to_string());
-    }))?;
+
+    let (files, skipped) = match &completed {
+        Some(completed) => resume_remaining(files, completed, output_path),
+        None => (files, 0),
+    };
+    let journal = Journal::open(output_path, &snapshot_id, completed.is_some())?;
+
+    eprintln!("Extracting to {}", output_path.display());
+    let pb = create_progress_bar(files.len() as u64);
+
+    let (total_files, total_size) = extract_files_parallel(
+        &store,
+        &files,
+        output_path,
+        &extract_options,
+        Some(&journal),
+        Some(&|current, total, msg| {
+            pb.set_length(total as u64);
+            pb.set_position(current as u64);
+            pb.set_message(msg.to_string());
+        }),
+    )?;
+    pb.finish_and_clear();
+    restore_directories(&directories, output_path)?;
     let elapsed = start.elapsed().as_secs_f64();
+
+    journal.finish()?;
+
+    let mut summary = vec![format!(
+        "Extracted: {} files ({})",
+        total_files,
+        format_bytes(total_size)
+    )];
+    if completed.is_some() {
+        summary.push(format!("Resumed: {} files already in place", skipped));
+    }
+    summary.push(format!("Time: {:.1}s", elapsed));
+
+    let line_refs: Vec<&str> = summary.iter().map(|s| s.as_str()).collect();
+    print_box("Unpack Complete", &line_refs, "\x1b[32m");
+
+    Ok(())
+}
+
+/// Removes the directories of `packages`, leaving any directory that also
+/// holds a package outside the selection (the root pseudo-package, a
+/// workspace's node_modules); their files are overwritten. Yarn PnP
+/// archives share `.yarn/cache` with whatever else Yarn keeps there, so
+/// only their own zips are removed.
+fn remove_package_dirs(
+    store: &Store,
+    output_path: &Path,
+    packages: &[PackageInfo],
+    all_packages: &[PackageInfo],
+) -> Result<()> {
+    let selected_ids: HashSet<Option<i64>> = packages.iter().map(|p| p.id).collect();
+
+    let (archives, packages): (Vec<PackageInfo>, Vec<PackageInfo>) = packages
+        .iter()
+        .cloned()
+        .partition(|p| p.path == PNP_CACHE_DIR);
+    let archive_ids: Vec<i64> = archives.iter().filter_map(|p| p.id).collect();
+    if !archive_ids.is_empty() {
+        let cache_path = output_path.join(PNP_CACHE_DIR);
+        eprintln!("Removing existing archives in {}...", cache_path.display());
+        for file in store.get_files_for_packages(&archive_ids)? {
+            let path = output_path.join(file.node_modules_path());
+            if let Err(e) = fs::remove_file(&path) {
+                if e.kind() != std::io::ErrorKind::NotFound {
+                    return Err(e.into());
+                }
+            }
+        }
+    }
+
+    for path in package_scopes(&packages) {
+        let nested = format!("{}/", path);
+        let shared = all_packages.iter().any(|p| {
+            !selected_ids.contains(&p.id) && (p.path == path || p.path.starts_with(&nested))
+        });
+        let pkg_path = output_path.join(&path);
+        if shared || !pkg_path.exists() {
+            continue;
+        }
+
+        eprintln!("Removing existing {}...", pkg_path.display());
+        fs::remove_dir_all(&pkg_path)?;
+    }
+
+    Ok(())
+}
+
+/// Splits off files a previous, interrupted unpack already wrote, keeping
+/// any whose on-disk content no longer matches the snapshot.
+fn resume_remaining(
+    files: Vec<FileRecordWithPath>,
+    completed: &HashSet<i64>,
+    output_path: &Path,
+) -> (Vec<FileRecordWithPath>, usize) {
+    let (done, mut remaining): (Vec<_>, Vec<_>) = files
+        .into_iter()
+        .partition(|f| f.record.id.is_some_and(|id| completed.contains(&id)));
+
+    eprintln!(
+        "Resuming: verifying {} previously extracted files",
+        done.len()
+    );
+    let result = compare_files(&done, output_path, Some(&[]), &[], None);
+    let outdated: HashSet<&str> = result
+        .modified
+        .iter()
+        .chain(result.only_in_db.iter())
+        .map(|s| s.as_str())
+        .collect();
+
+    let (invalid, valid): (Vec<_>, Vec<_>) = done
+        .into_iter()
+        .partition(|f| outdated.contains(f.node_modules_path().as_str()));
+    remaining.extend(invalid);
+
+    (remaining, valid.len())
+}
+
+fn sync_files(
+    store: &Store,
+    files: &[FileRecordWithPath],
+    directories: &[DirectoryRecord],
+    output_path: &Path,
+    extract_options: &ExtractOptions,
+    scopes: Option<&[String]>,
+    node_modules_roots: &[String],
+) -> Result<()> {
+    let start = Instant::now();
+
+    eprintln!("Comparing with {}", output_path.display());
+    let pb = create_progress_bar(files.len() as u64);
+    let mut result = compare_files(
+        files,
+        output_path,
+        scopes,
+        node_modules_roots,
+        Some(&|current, _total, msg| {
+            pb.set_position(current as u64);
+            pb.set_message(msg.to_string());
+        }),
+    );
+    pb.finish_and_clear();
+
+    // Leave files the snapshot excluded on purpose in place.
+    let exclude = ExcludeFilter::from_metadata(store.get_metadata("exclude")?.as_deref())?;
+    drop_excluded(&mut result, &store.get_packages()?, &exclude);
+
+    for relative_path in &result.only_in_fs {
+        let full_path = output_path.join(relative_path);
+        fs::remove_file(&full_path)?;
+        remove_empty_parents(&full_path, output_path);
+    }
+
+    let outdated: HashSet<&str> = result
+        .modified
+        .iter()
+        .chain(result.only_in_db.iter())
+        .map(|s| s.as_str())
+        .collect();
+    let to_write: Vec<FileRecordWithPath> = files
+        .iter()
+        .filter(|f| outdated.contains(f.node_modules_path().as_str()))
+        .cloned()
+        .collect();
+
+    let pb = create_progress_bar(to_write.len() as u64);
+    let (written, written_size) = extract_files_parallel(
+        store,
+        &to_write,
+        output_path,
+        extract_options,
+        None,
+        Some(&|current, total, msg| {
+            pb.set_length(total as u64);
+            pb.set_position(current as u64);
+            pb.set_message(msg.to_string());
+        }),
+    )?;
     pb.finish_and_clear();
+    restore_directories(directories, output_path)?;
+
+    let elapsed = start.elapsed().as_secs_f64();
 
     print_box(
-        "Unpack Complete",
+        "Sync Complete",
         &[
-            &format!("Extracted: {} files ({})", total_files, format_bytes(total_size)),
+            &format!(
+                "Written: {} files ({})",
+                written,
+                format_bytes(written_size)
+            ),
+            &format!("Deleted: {} files", result.only_in_fs.len()),
+            &format!("Unchanged: {} files", result.unchanged),
             &format!("Time: {:.1}s", elapsed),
         ],
         "\x1b[32m",
@@ -76,3 +358,13 @@ pub fn unpack(options: &UnpackOptions) -> Result<()> {
 
     Ok(())
 }
+
+fn remove_empty_parents(path: &Path, root: &Path) {
+    let mut current = path.parent();
+    while let Some(dir) = current {
+        if dir == root || !dir.starts_with(root) || fs::remove_dir(dir).is_err() {
+            break;
+        }
+        current = dir.parent();
+    }
+}
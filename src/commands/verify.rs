@@ -0,0 +1,198 @@
+use anyhow::{bail, Result};
+use rayon::prelude::*;
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::commands::pack::print_box;
+use crate::core::differ::{diff_against_pristine, FileChanges};
+use crate::core::exclude::ExcludeFilter;
+use crate::core::npm_cache::{
+    cacache_dir, read_cached_tarball, read_locked_packages, tarball_file_hashes, LockedPackage,
+};
+use crate::core::scanner::ROOT_PACKAGE_NAME;
+use crate::core::store::Store;
+use crate::types::{Layout, VerifyOptions};
+use crate::utils::progress::create_progress_bar;
+
+/// How a recorded package compares with its registry tarball.
+enum Check {
+    Pristine,
+    Modified(FileChanges),
+    /// The lockfile pins another version than the snapshot holds.
+    VersionDiffers(String),
+    NotLocked,
+    NotCached,
+    /// The integrity only has a sha1 hash, so the cached tarball can't be
+    /// trusted for the comparison.
+    Unverified,
+    /// The cached tarball failed its integrity check or couldn't be read.
+    Corrupt(String),
+}
+
+/// Compares each package's files with the pristine tarball from the npm
+/// cache, found through the `integrity` in package-lock.json, to flag
+/// packages changed after install. Only reads the DB and the cache.
+pub fn verify(options: &VerifyOptions) -> Result<()> {
+    let db_path = Path::new(&options.db);
+
+    if !db_path.exists() {
+        bail!("Database not found: {}", db_path.display());
+    }
+
+    let store = Store::open_readonly(db_path.to_str().unwrap_or_default())?;
+    let layout = Layout::from_metadata(store.get_metadata("layout")?.as_deref());
+    if layout == Layout::YarnPnp {
+        bail!("verify is not supported for Yarn PnP snapshots");
+    }
+
+    let lockfile_path = Path::new(&options.lockfile);
+    if !lockfile_path.exists() {
+        bail!("Lockfile not found: {}", lockfile_path.display());
+    }
+    let locked = read_locked_packages(lockfile_path)?;
+    let locked_by_path: HashMap<&str, &LockedPackage> =
+        locked.iter().map(|p| (p.path.as_str(), p)).collect();
+
+    let cacache = cacache_dir(options.cache.as_deref());
+    if !cacache.is_dir() {
+        bail!("npm cache not found: {}", cacache.display());
+    }
+
+    // Files the snapshot left out on purpose aren't modifications.
+    let exclude = ExcludeFilter::from_metadata(store.get_metadata("exclude")?.as_deref())?;
+
+    let mut packages: Vec<_> = store
+        .get_packages()?
+        .into_iter()
+        .filter(|p| p.name != ROOT_PACKAGE_NAME)
+        .collect();
+    packages.sort_by(|a, b| a.path.cmp(&b.path));
+
+    let mut files: HashMap<i64, BTreeMap<String, String>> = HashMap::new();
+    for file in store.get_all_files()? {
+        files
+            .entry(file.record.package_id)
+            .or_default()
+            .insert(file.record.relative_path, file.record.blob_hash);
+    }
+    let no_files = BTreeMap::new();
+
+    // Lockfile keys are project-relative (`node_modules/a`).
+    let prefix = if layout.is_project_rooted() {
+        ""
+    } else {
+        "node_modules/"
+    };
+
+    eprintln!("Verifying against {}...", cacache.display());
+    let pb = create_progress_bar(packages.len() as u64);
+    let processed = AtomicUsize::new(0);
+
+    let checks: Vec<Check> = packages
+        .par_iter()
+        .map(|pkg| {
+            pb.set_position(processed.fetch_add(1, Ordering::Relaxed) as u64 + 1);
+            pb.set_message(pkg.name.clone());
+
+            let Some(locked) = locked_by_path.get(format!("{}{}", prefix, pkg.path).as_str())
+            else {
+                return Check::NotLocked;
+            };
+            if let Some(version) = locked.version.as_ref().filter(|v| **v != pkg.version) {
+                return Check::VersionDiffers(version.clone());
+            }
+            let Some(integrity) = locked.integrity.as_deref() else {
+                return Check::NotCached;
+            };
+            let tarball = match read_cached_tarball(&cacache, integrity) {
+                Ok(Some(tarball)) => tarball,
+                Ok(None) => return Check::NotCached,
+                Err(e) => return Check::Corrupt(e.to_string()),
+            };
+            if !tarball.verified {
+                return Check::Unverified;
+            }
+
+            let pristine = match tarball_file_hashes(&tarball.content) {
+                Ok(pristine) => pristine,
+                Err(e) => return Check::Corrupt(format!("unreadable tarball: {}", e)),
+            };
+            let snapshot = pkg.id.and_then(|id| files.get(&id)).unwrap_or(&no_files);
+            let changes = diff_against_pristine(&pristine, snapshot, &exclude);
+            if changes.is_empty() {
+                Check::Pristine
+            } else {
+                Check::Modified(changes)
+            }
+        })
+        .collect();
+    pb.finish_and_clear();
+
+    let (mut pristine, mut modified, mut version_differs, mut not_locked, mut not_cached) =
+        (0, 0, 0, 0, 0);
+    let (mut unverified, mut corrupt) = (0, 0);
+    for (pkg, check) in packages.iter().zip(&checks) {
+        match check {
+            Check::Pristine => pristine += 1,
+            Check::Modified(changes) => {
+                modified += 1;
+                println!("~ {}@{} ({})", pkg.name, pkg.version, pkg.path);
+                for path in &changes.added {
+                    println!("    A {}", path);
+                }
+                for path in &changes.removed {
+                    println!("    D {}", path);
+                }
+                for path in &changes.modified {
+                    println!("    M {}", path);
+                }
+            }
+            Check::VersionDiffers(version) => {
+                version_differs += 1;
+                println!(
+                    "! {}@{} ({}): lockfile has {}",
+                    pkg.name, pkg.version, pkg.path, version
+                );
+            }
+            Check::NotLocked => not_locked += 1,
+            Check::NotCached => not_cached += 1,
+            Check::Unverified => {
+                unverified += 1;
+                println!(
+                    "? {}@{} ({}): sha1 integrity, tarball not verified",
+                    pkg.name, pkg.version, pkg.path
+                );
+            }
+            Check::Corrupt(reason) => {
+                corrupt += 1;
+                println!("x {}@{} ({}): {}", pkg.name, pkg.version, pkg.path, reason);
+            }
+        }
+    }
+
+    let color = if modified + version_differs + corrupt == 0 {
+        "\x1b[32m"
+    } else {
+        "\x1b[33m"
+    };
+    print_box(
+        "Verify",
+        &[
+            &format!("Pristine: {}", pristine),
+            &format!("Modified after install: {}", modified),
+            &format!("Version differs from lockfile: {}", version_differs),
+            &format!("Not in lockfile: {}", not_locked),
+            &format!("Not in npm cache: {}", not_cached),
+            &format!("Unverified (sha1 integrity): {}", unverified),
+            &format!("Corrupt in npm cache: {}", corrupt),
+        ],
+        color,
+    );
+
+    if modified + version_differs + corrupt == 0 && pristine > 0 {
+        eprintln!("All cached packages match their tarballs!");
+    }
+
+    Ok(())
+}
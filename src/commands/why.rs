@@ -0,0 +1,109 @@
+use anyhow::{bail, Result};
+use std::collections::HashSet;
+use std::path::Path;
+
+use crate::core::filter::PackageFilter;
+use crate::core::graph::{DependencyGraph, Dependent};
+use crate::core::store::Store;
+use crate::types::{Dependency, DependencyKind, PackageInfo};
+
+/// Prints every chain of dependency declarations leading to the packages
+/// matching `spec`, like `npm explain`.
+pub fn why(db: &str, spec: &str) -> Result<()> {
+    let db_path = Path::new(db);
+
+    if !db_path.exists() {
+        bail!("Database not found: {}", db_path.display());
+    }
+
+    let store = Store::open_readonly(db_path.to_str().unwrap_or_default())?;
+    // Snapshots from before schema 4 lack the project's own dependencies,
+    // so every top-level package would look unrequired.
+    if !store.has_table("dependencies")? || !store.has_table("root_dependencies")? {
+        bail!(
+            "{} has no dependency information; re-pack it with this version",
+            db_path.display()
+        );
+    }
+
+    let filter = PackageFilter::new(&[spec.to_string()])?;
+    let graph = DependencyGraph::load(&store)?;
+
+    let targets: Vec<usize> = graph
+        .packages()
+        .iter()
+        .enumerate()
+        .filter(|(_, pkg)| filter.matches(pkg))
+        .map(|(index, _)| index)
+        .collect();
+    if targets.is_empty() {
+        bail!("No packages match: {}", spec);
+    }
+
+    for (n, &target) in targets.iter().enumerate() {
+        if n > 0 {
+            println!();
+        }
+        println!("{}", describe(&graph.packages()[target]));
+
+        if graph.dependents(target).is_empty() {
+            println!("  (not required by any recorded package)");
+            continue;
+        }
+
+        let mut stack = vec![target];
+        let mut expanded = HashSet::new();
+        print_dependents(&graph, target, 1, &mut stack, &mut expanded);
+    }
+
+    Ok(())
+}
+
+fn print_dependents(
+    graph: &DependencyGraph,
+    index: usize,
+    depth: usize,
+    stack: &mut Vec<usize>,
+    expanded: &mut HashSet<usize>,
+) {
+    let indent = "  ".repeat(depth);
+
+    for (dependent, dependency) in graph.dependents(index) {
+        let edge = format_edge(dependency);
+        match dependent {
+            Dependent::Importer(importer) if importer.is_empty() => {
+                println!("{}{} from the root project", indent, edge);
+            }
+            Dependent::Importer(importer) => {
+                println!("{}{} from workspace {}", indent, edge, importer);
+            }
+            Dependent::Package(from) => {
+                let pkg = describe(&graph.packages()[*from]);
+                if stack.contains(from) {
+                    println!("{}{} from {} (circular)", indent, edge, pkg);
+                } else if !expanded.insert(*from) {
+                    println!("{}{} from {} (see above)", indent, edge, pkg);
+                } else {
+                    println!("{}{} from {}", indent, edge, pkg);
+                    stack.push(*from);
+                    print_dependents(graph, *from, depth + 1, stack, expanded);
+                    stack.pop();
+                }
+            }
+        }
+    }
+}
+
+fn describe(pkg: &PackageInfo) -> String {
+    format!("{}@{} ({})", pkg.name, pkg.version, pkg.path)
+}
+
+fn format_edge(dependency: &Dependency) -> String {
+    let prefix = match dependency.kind {
+        DependencyKind::Dependencies => "",
+        DependencyKind::PeerDependencies => "peer ",
+        DependencyKind::OptionalDependencies => "optional ",
+        DependencyKind::DevDependencies => "dev ",
+    };
+    format!("{}{}@\"{}\"", prefix, dependency.name, dependency.spec)
+}
@@ -0,0 +1,499 @@
+use anyhow::Result;
+use rayon::prelude::*;
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use walkdir::WalkDir;
+
+use crate::core::exclude::ExcludeFilter;
+use crate::core::hasher::hash_buffer;
+use crate::core::scanner::{list_package_dirs, read_package_identity, ROOT_PACKAGE_NAME};
+use crate::types::{
+    FileRecordWithPath, PackageInfo, PackageState, PackageStatus, ProgressFn, StatusResult,
+};
+
+/// Top-level node_modules entries the scanner never snapshots.
+const UNTRACKED_IGNORED_DIRS: [&str; 2] = [".bin", ".cache"];
+
+/// Compares DB file records against the files under `node_modules_path`.
+///
+/// Paths in the result are relative to `node_modules_path`. When `scopes` is
+/// given, untracked files are only searched for inside those package paths.
+/// `node_modules_roots` are the node_modules directories below
+/// `node_modules_path` (see `Layout::node_modules_roots`).
+pub fn compare_files(
+    files: &[FileRecordWithPath],
+    node_modules_path: &Path,
+    scopes: Option<&[String]>,
+    node_modules_roots: &[String],
+    on_progress: Option<&ProgressFn<'_>>,
+) -> StatusResult {
+    let total = files.len();
+    let processed = AtomicUsize::new(0);
+
+    let result = Mutex::new(StatusResult::default());
+    let db_paths = Mutex::new(HashSet::new());
+
+    files.par_iter().for_each(|file| {
+        let relative_path = file.node_modules_path();
+        let full_path = node_modules_path.join(&relative_path);
+
+        db_paths.lock().unwrap().insert(relative_path.clone());
+
+        if let Some(progress) = on_progress {
+            let display = &file.record.relative_path;
+            let display = display
+                .char_indices()
+                .nth(40)
+                .map_or(display.as_str(), |(i, _)| &display[..i]);
+            progress(
+                processed.fetch_add(1, Ordering::Relaxed) + 1,
+                total,
+                display,
+            );
+        }
+
+        if !full_path.exists() {
+            result.lock().unwrap().only_in_db.push(relative_path);
+            return;
+        }
+
+        match std::fs::read(&full_path) {
+            Ok(content) => {
+                let fs_hash = hash_buffer(&content);
+                if fs_hash != file.record.blob_hash {
+                    result.lock().unwrap().modified.push(relative_path);
+                } else {
+                    result.lock().unwrap().unchanged += 1;
+                }
+            }
+            Err(_) => {
+                result.lock().unwrap().modified.push(relative_path);
+            }
+        }
+    });
+
+    let mut result = result.into_inner().unwrap();
+    let db_paths = db_paths.into_inner().unwrap();
+
+    result.only_in_fs = find_untracked(node_modules_path, &db_paths, scopes, node_modules_roots);
+
+    result.modified.sort();
+    result.only_in_db.sort();
+    result.only_in_fs.sort();
+
+    result
+}
+
+/// Distinct package directories to search for untracked files, skipping the
+/// root pseudo-package (whose files sit directly in the root).
+pub fn package_scopes(packages: &[PackageInfo]) -> Vec<String> {
+    let mut scopes: Vec<String> = packages
+        .iter()
+        .filter(|p| !p.path.is_empty())
+        .map(|p| p.path.clone())
+        .collect();
+    scopes.sort();
+    scopes.dedup();
+    scopes
+}
+
+/// Aggregates a `compare_files` result by package, adding packages installed
+/// in the node_modules roots but missing from the snapshot and installed
+/// versions that differ from the recorded ones. Unchanged packages are left
+/// out.
+pub fn summarize_packages(
+    files: &[FileRecordWithPath],
+    packages: &[PackageInfo],
+    result: &StatusResult,
+    node_modules_path: &Path,
+    node_modules_roots: &[String],
+) -> Result<Vec<PackageStatus>> {
+    let mut statuses: Vec<PackageStatus> = packages
+        .iter()
+        .map(|p| PackageStatus {
+            name: p.name.clone(),
+            version: p.version.clone(),
+            path: p.path.clone(),
+            state: PackageState::Changed,
+            installed_version: None,
+            modified: 0,
+            deleted: 0,
+            untracked: 0,
+        })
+        .collect();
+
+    let index_by_id: HashMap<i64, usize> = packages
+        .iter()
+        .enumerate()
+        .filter_map(|(i, p)| Some((p.id?, i)))
+        .collect();
+    let mut file_counts = vec![0usize; packages.len()];
+    let mut file_owner = HashMap::new();
+    for file in files {
+        if let Some(&index) = index_by_id.get(&file.record.package_id) {
+            file_counts[index] += 1;
+            file_owner.insert(file.node_modules_path(), index);
+        }
+    }
+
+    for path in &result.modified {
+        if let Some(&index) = file_owner.get(path) {
+            statuses[index].modified += 1;
+        }
+    }
+    for path in &result.only_in_db {
+        if let Some(&index) = file_owner.get(path) {
+            statuses[index].deleted += 1;
+        }
+    }
+
+    let mut by_path: HashMap<String, usize> = packages
+        .iter()
+        .enumerate()
+        .map(|(i, p)| (p.path.clone(), i))
+        .collect();
+
+    for root in node_modules_roots {
+        let root_path = node_modules_path.join(root);
+        if !root_path.is_dir() {
+            continue;
+        }
+        for relative in list_package_dirs(&root_path)? {
+            let path = if root.is_empty() {
+                relative
+            } else {
+                format!("{}/{}", root, relative)
+            };
+            if by_path.contains_key(&path) {
+                continue;
+            }
+            let pkg_json_path = node_modules_path.join(&path).join("package.json");
+            let Some((name, version)) = read_package_identity(&pkg_json_path) else {
+                continue;
+            };
+            by_path.insert(path.clone(), statuses.len());
+            statuses.push(PackageStatus {
+                name,
+                version,
+                path,
+                state: PackageState::Untracked,
+                installed_version: None,
+                modified: 0,
+                deleted: 0,
+                untracked: 0,
+            });
+        }
+    }
+
+    for path in &result.only_in_fs {
+        if let Some(index) = owning_package(path, &by_path) {
+            statuses[index].untracked += 1;
+        }
+    }
+
+    for (index, pkg) in packages.iter().enumerate() {
+        let status = &mut statuses[index];
+        if file_counts[index] > 0 && status.deleted == file_counts[index] {
+            status.state = PackageState::Missing;
+            continue;
+        }
+        if pkg.name == ROOT_PACKAGE_NAME {
+            continue;
+        }
+        let pkg_json_path = node_modules_path.join(&pkg.path).join("package.json");
+        if let Some((_, installed)) = read_package_identity(&pkg_json_path) {
+            if installed != pkg.version {
+                status.installed_version = Some(installed);
+            }
+        }
+    }
+
+    statuses.retain(|s| {
+        s.state != PackageState::Changed
+            || s.installed_version.is_some()
+            || s.modified + s.deleted + s.untracked > 0
+    });
+    statuses.sort_by(|a, b| a.path.cmp(&b.path));
+
+    Ok(statuses)
+}
+
+/// Drops untracked files the snapshot left out on purpose: files inside a
+/// recorded package that match its exclude patterns. Like `pack`, a
+/// package's own `package.json` is never excluded.
+pub fn drop_excluded(result: &mut StatusResult, packages: &[PackageInfo], exclude: &ExcludeFilter) {
+    if exclude.is_empty() {
+        return;
+    }
+
+    let by_path: HashMap<String, usize> = packages
+        .iter()
+        .enumerate()
+        .map(|(i, p)| (p.path.clone(), i))
+        .collect();
+
+    result.only_in_fs.retain(|path| {
+        let Some(index) = owning_package(path, &by_path) else {
+            return true;
+        };
+        let package_path = &packages[index].path;
+        let relative_path = if package_path.is_empty() {
+            path.as_str()
+        } else {
+            &path[package_path.len() + 1..]
+        };
+        relative_path == "package.json" || !exclude.is_excluded(relative_path)
+    });
+}
+
+/// The package whose directory is the closest ancestor of `path`.
+fn owning_package(path: &str, by_path: &HashMap<String, usize>) -> Option<usize> {
+    let mut dir = path;
+    while let Some((parent, _)) = dir.rsplit_once('/') {
+        if let Some(&index) = by_path.get(parent) {
+            return Some(index);
+        }
+        dir = parent;
+    }
+    by_path.get("").copied()
+}
+
+fn find_untracked(
+    node_modules_path: &Path,
+    db_paths: &HashSet<String>,
+    scopes: Option<&[String]>,
+    node_modules_roots: &[String],
+) -> Vec<String> {
+    let roots: Vec<_> = match scopes {
+        Some(scopes) => scopes.iter().map(|s| node_modules_path.join(s)).collect(),
+        None => vec![node_modules_path.to_path_buf()],
+    };
+
+    let mut untracked = Vec::new();
+
+    for root in roots {
+        if !root.exists() {
+            continue;
+        }
+
+        for entry in WalkDir::new(&root).into_iter().filter_map(|e| e.ok()) {
+            if !entry.file_type().is_file() {
+                continue;
+            }
+
+            let Ok(relative) = entry.path().strip_prefix(node_modules_path) else {
+                continue;
+            };
+            let relative = relative.to_string_lossy().replace('\\', "/");
+
+            if is_ignored(&relative, node_modules_roots) || db_paths.contains(&relative) {
+                continue;
+            }
+
+            untracked.push(relative);
+        }
+    }
+
+    untracked
+}
+
+/// Whether an untracked file should be left alone: anything outside the
+/// node_modules roots, or entries the scanner never snapshots inside them.
+fn is_ignored(relative_path: &str, node_modules_roots: &[String]) -> bool {
+    let stripped = node_modules_roots.iter().find_map(|root| {
+        if root.is_empty() {
+            Some(relative_path)
+        } else {
+            relative_path
+                .strip_prefix(root.as_str())
+                .and_then(|rest| rest.strip_prefix('/'))
+        }
+    });
+    match stripped {
+        Some(relative_path) => is_ignored_in_node_modules(relative_path),
+        None => false,
+    }
+}
+
+fn is_ignored_in_node_modules(relative_path: &str) -> bool {
+    let Some((top, rest)) = relative_path.split_once('/') else {
+        // Root-level metadata files are not part of any package, and older
+        // snapshots don't contain them, so never report them as untracked.
+        return true;
+    };
+
+    if UNTRACKED_IGNORED_DIRS.contains(&top) {
+        return true;
+    }
+
+    top == ".pnpm" && (rest.starts_with("node_modules/") || !rest.contains("/node_modules/"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_ignored() {
+        let roots = vec![String::new()];
+        assert!(is_ignored(".package-lock.json", &roots));
+        assert!(is_ignored(".bin/tsc", &roots));
+        assert!(is_ignored(".cache/babel/x.json", &roots));
+        assert!(is_ignored(".pnpm/lock.yaml", &roots));
+        assert!(is_ignored(".pnpm/node_modules/lodash/index.js", &roots));
+        assert!(!is_ignored(
+            ".pnpm/lodash@4.17.21/node_modules/lodash/index.js",
+            &roots
+        ));
+        assert!(!is_ignored("lodash/index.js", &roots));
+        assert!(!is_ignored("@scope/pkg/lib/a.js", &roots));
+    }
+
+    #[test]
+    fn test_summarize_packages() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        for (name, version) in [("lodash", "4.17.20"), ("new-pkg", "1.0.0")] {
+            std::fs::create_dir_all(root.join(name)).unwrap();
+            std::fs::write(
+                root.join(name).join("package.json"),
+                format!(r#"{{"name":"{}","version":"{}"}}"#, name, version),
+            )
+            .unwrap();
+        }
+        std::fs::write(root.join("lodash/index.js"), "changed").unwrap();
+
+        let packages = vec![
+            PackageInfo {
+                id: Some(1),
+                name: "lodash".to_string(),
+                version: "4.17.21".to_string(),
+                path: "lodash".to_string(),
+            },
+            PackageInfo {
+                id: Some(2),
+                name: "left-pad".to_string(),
+                version: "1.3.0".to_string(),
+                path: "left-pad".to_string(),
+            },
+        ];
+        let file = |package_id: i64, package_path: &str, relative_path: &str| FileRecordWithPath {
+            record: crate::types::FileRecord {
+                id: None,
+                package_id,
+                relative_path: relative_path.to_string(),
+                blob_hash: "0".repeat(64),
+                mode: 0o644,
+                mtime: 0,
+            },
+            package_path: package_path.to_string(),
+        };
+        let files = vec![
+            file(1, "lodash", "package.json"),
+            file(1, "lodash", "index.js"),
+            file(2, "left-pad", "package.json"),
+        ];
+        let roots = vec![String::new()];
+
+        let result = compare_files(&files, root, None, &roots, None);
+        let statuses = summarize_packages(&files, &packages, &result, root, &roots).unwrap();
+
+        let summary: Vec<_> = statuses
+            .iter()
+            .map(|s| {
+                (
+                    s.name.as_str(),
+                    s.state,
+                    s.installed_version.as_deref(),
+                    s.modified,
+                )
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                ("left-pad", PackageState::Missing, None, 0),
+                ("lodash", PackageState::Changed, Some("4.17.20"), 2),
+                ("new-pkg", PackageState::Untracked, None, 0),
+            ]
+        );
+        assert_eq!(statuses[2].untracked, 1);
+    }
+
+    #[test]
+    fn test_drop_excluded() {
+        let packages = vec![PackageInfo {
+            id: Some(1),
+            name: "lodash".to_string(),
+            version: "4.17.21".to_string(),
+            path: "lodash".to_string(),
+        }];
+        let mut result = StatusResult {
+            only_in_fs: vec![
+                "lodash/README.md".to_string(),
+                "lodash/lib/index.js".to_string(),
+                "lodash/package.json".to_string(),
+                "new-pkg/README.md".to_string(),
+            ],
+            ..Default::default()
+        };
+        let exclude =
+            ExcludeFilter::new(&["*.md".to_string(), "package.json".to_string()]).unwrap();
+
+        drop_excluded(&mut result, &packages, &exclude);
+
+        assert_eq!(
+            result.only_in_fs,
+            vec![
+                "lodash/lib/index.js",
+                "lodash/package.json",
+                "new-pkg/README.md"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_compare_files_multibyte_progress() {
+        let dir = tempfile::tempdir().unwrap();
+        let relative_path = format!("a{}.js", "ü".repeat(60));
+        let files = vec![FileRecordWithPath {
+            record: crate::types::FileRecord {
+                id: None,
+                package_id: 1,
+                relative_path: relative_path.clone(),
+                blob_hash: "0".repeat(64),
+                mode: 0o644,
+                mtime: 0,
+            },
+            package_path: "pkg".to_string(),
+        }];
+
+        let shown = Mutex::new(String::new());
+        let on_progress = |_: usize, _: usize, msg: &str| *shown.lock().unwrap() = msg.to_string();
+        let roots = vec![String::new()];
+        let result = compare_files(&files, dir.path(), None, &roots, Some(&on_progress));
+
+        assert_eq!(result.only_in_db, vec![format!("pkg/{}", relative_path)]);
+        assert_eq!(shown.into_inner().unwrap().chars().count(), 40);
+    }
+
+    #[test]
+    fn test_is_ignored_project_rooted() {
+        let roots = vec![
+            "node_modules".to_string(),
+            "packages/app/node_modules".to_string(),
+        ];
+        assert!(is_ignored("node_modules/.package-lock.json", &roots));
+        assert!(is_ignored("packages/app/node_modules/.bin/tsc", &roots));
+        assert!(!is_ignored(
+            "packages/app/node_modules/react/index.js",
+            &roots
+        ));
+        assert!(!is_ignored(
+            ".yarn/cache/lodash-npm-4.17.21-abc-def.zip",
+            &[]
+        ));
+    }
+}
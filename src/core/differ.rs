@@ -0,0 +1,213 @@
+use std::collections::{BTreeMap, HashMap};
+
+use crate::core::exclude::ExcludeFilter;
+use crate::types::PackageInfo;
+
+/// How a package differs between two snapshots. Indices point into the old
+/// and new package lists.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PackageChange {
+    Added(usize),
+    Removed(usize),
+    /// Present in both, possibly at another version or path.
+    Kept {
+        old: usize,
+        new: usize,
+    },
+}
+
+/// Files of one package that differ, by path relative to the package.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FileChanges {
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+    pub modified: Vec<String>,
+}
+
+impl FileChanges {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.modified.is_empty()
+    }
+}
+
+/// Pairs packages across two snapshots: first by path, then leftover
+/// packages with the same name when each side has exactly one, which is how
+/// a version bump looks in pnpm's versioned `.pnpm/<name>@<version>` paths.
+pub fn match_packages(old: &[PackageInfo], new: &[PackageInfo]) -> Vec<PackageChange> {
+    let new_by_path: HashMap<&str, usize> = new
+        .iter()
+        .enumerate()
+        .map(|(i, p)| (p.path.as_str(), i))
+        .collect();
+
+    let mut changes = Vec::new();
+    let mut matched_new = vec![false; new.len()];
+    let mut unmatched_old: BTreeMap<&str, Vec<usize>> = BTreeMap::new();
+
+    for (i, pkg) in old.iter().enumerate() {
+        match new_by_path.get(pkg.path.as_str()) {
+            Some(&j) if new[j].name == pkg.name => {
+                matched_new[j] = true;
+                changes.push(PackageChange::Kept { old: i, new: j });
+            }
+            _ => unmatched_old.entry(&pkg.name).or_default().push(i),
+        }
+    }
+
+    let mut unmatched_new: BTreeMap<&str, Vec<usize>> = BTreeMap::new();
+    for (j, pkg) in new.iter().enumerate() {
+        if !matched_new[j] {
+            unmatched_new.entry(&pkg.name).or_default().push(j);
+        }
+    }
+
+    for (name, old_indices) in &unmatched_old {
+        match unmatched_new.remove(name) {
+            Some(new_indices) if old_indices.len() == 1 && new_indices.len() == 1 => {
+                changes.push(PackageChange::Kept {
+                    old: old_indices[0],
+                    new: new_indices[0],
+                });
+            }
+            Some(new_indices) => {
+                changes.extend(old_indices.iter().map(|&i| PackageChange::Removed(i)));
+                changes.extend(new_indices.into_iter().map(PackageChange::Added));
+            }
+            None => changes.extend(old_indices.iter().map(|&i| PackageChange::Removed(i))),
+        }
+    }
+    for new_indices in unmatched_new.into_values() {
+        changes.extend(new_indices.into_iter().map(PackageChange::Added));
+    }
+
+    changes
+}
+
+/// Compares two `relative_path -> blob hash` maps.
+pub fn diff_files(old: &BTreeMap<String, String>, new: &BTreeMap<String, String>) -> FileChanges {
+    let mut changes = FileChanges::default();
+
+    for (path, hash) in old {
+        match new.get(path) {
+            None => changes.removed.push(path.clone()),
+            Some(new_hash) if new_hash != hash => changes.modified.push(path.clone()),
+            Some(_) => {}
+        }
+    }
+    for path in new.keys() {
+        if !old.contains_key(path) {
+            changes.added.push(path.clone());
+        }
+    }
+
+    changes
+}
+
+/// Compares a package's snapshot files with its pristine tarball. Nested
+/// `node_modules` (other packages) are ignored, as are tarball files the
+/// snapshot's exclude patterns left out. `added` files exist only in the
+/// snapshot, `removed` ones only in the tarball.
+pub fn diff_against_pristine(
+    pristine: &BTreeMap<String, String>,
+    snapshot: &BTreeMap<String, String>,
+    exclude: &ExcludeFilter,
+) -> FileChanges {
+    let is_nested =
+        |path: &str| path.starts_with("node_modules/") || path.contains("/node_modules/");
+
+    let pristine: BTreeMap<String, String> = pristine
+        .iter()
+        .filter(|(path, _)| {
+            !is_nested(path) && (path.as_str() == "package.json" || !exclude.is_excluded(path))
+        })
+        .map(|(path, hash)| (path.clone(), hash.clone()))
+        .collect();
+    let snapshot: BTreeMap<String, String> = snapshot
+        .iter()
+        .filter(|(path, _)| !is_nested(path))
+        .map(|(path, hash)| (path.clone(), hash.clone()))
+        .collect();
+
+    diff_files(&pristine, &snapshot)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pkg(name: &str, version: &str, path: &str) -> PackageInfo {
+        PackageInfo {
+            id: None,
+            name: name.to_string(),
+            version: version.to_string(),
+            path: path.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_match_packages() {
+        let old = vec![
+            pkg("lodash", "4.17.20", "lodash"),
+            pkg("ms", "2.1.2", ".pnpm/ms@2.1.2/node_modules/ms"),
+            pkg("left-pad", "1.3.0", "left-pad"),
+        ];
+        let new = vec![
+            pkg("lodash", "4.17.21", "lodash"),
+            pkg("ms", "2.1.3", ".pnpm/ms@2.1.3/node_modules/ms"),
+            pkg("chalk", "5.0.0", "chalk"),
+        ];
+
+        let mut changes = match_packages(&old, &new);
+        changes.sort_by_key(|c| format!("{:?}", c));
+
+        assert_eq!(
+            changes,
+            vec![
+                PackageChange::Added(2),
+                PackageChange::Kept { old: 0, new: 0 },
+                PackageChange::Kept { old: 1, new: 1 },
+                PackageChange::Removed(2),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_diff_files() {
+        let old: BTreeMap<String, String> = [("a.js", "1"), ("b.js", "2"), ("c.js", "3")]
+            .into_iter()
+            .map(|(p, h)| (p.to_string(), h.to_string()))
+            .collect();
+        let new: BTreeMap<String, String> = [("a.js", "1"), ("b.js", "9"), ("d.js", "4")]
+            .into_iter()
+            .map(|(p, h)| (p.to_string(), h.to_string()))
+            .collect();
+
+        let changes = diff_files(&old, &new);
+        assert_eq!(changes.added, vec!["d.js"]);
+        assert_eq!(changes.removed, vec!["c.js"]);
+        assert_eq!(changes.modified, vec!["b.js"]);
+    }
+
+    #[test]
+    fn test_diff_against_pristine() {
+        let map = |entries: &[(&str, &str)]| -> BTreeMap<String, String> {
+            entries
+                .iter()
+                .map(|(p, h)| (p.to_string(), h.to_string()))
+                .collect()
+        };
+        let pristine = map(&[("index.js", "1"), ("README.md", "2"), ("lib/a.js", "3")]);
+        let snapshot = map(&[
+            ("index.js", "9"),
+            ("lib/a.js", "3"),
+            ("build/addon.node", "4"),
+            ("node_modules/debug/index.js", "5"),
+        ]);
+        let exclude = ExcludeFilter::new(&["*.md".to_string()]).unwrap();
+
+        let changes = diff_against_pristine(&pristine, &snapshot, &exclude);
+        assert_eq!(changes.added, vec!["build/addon.node"]);
+        assert!(changes.removed.is_empty());
+        assert_eq!(changes.modified, vec!["index.js"]);
+    }
+}
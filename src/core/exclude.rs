@@ -0,0 +1,192 @@
+use anyhow::{Context, Result};
+use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
+use std::fs;
+use std::path::Path;
+
+use crate::core::scanner::ScannedPackage;
+
+/// Ignore file read from the project root by `pack`.
+pub const IGNORE_FILE_NAME: &str = ".mohyungignore";
+
+/// Patterns of the built-in `--prune` profile.
+pub const PRUNE_PATTERNS: [&str; 6] = ["*.md", "test/", "__tests__/", "*.map", "docs/", ".github/"];
+
+/// Gitignore-style exclusion of files inside packages.
+///
+/// Patterns are matched against paths relative to each package directory.
+/// A pattern without a `/` matches a file or directory name at any depth
+/// (`*.map`); one with a `/` is anchored to the package root (`lib/*.d.ts`).
+/// A trailing `/` only matches directories (`test/`).
+#[derive(Debug, Clone)]
+pub struct ExcludeFilter {
+    names: GlobSet,
+    dir_names: GlobSet,
+    paths: GlobSet,
+    dir_paths: GlobSet,
+    empty: bool,
+}
+
+impl ExcludeFilter {
+    pub fn new(patterns: &[String]) -> Result<Self> {
+        let mut names = GlobSetBuilder::new();
+        let mut dir_names = GlobSetBuilder::new();
+        let mut paths = GlobSetBuilder::new();
+        let mut dir_paths = GlobSetBuilder::new();
+
+        for pattern in patterns {
+            let dir_only = pattern.ends_with('/');
+            let trimmed = pattern.trim_end_matches('/');
+            let anchored = trimmed.contains('/');
+            let trimmed = trimmed.trim_start_matches('/');
+            if trimmed.is_empty() {
+                continue;
+            }
+
+            // `*` must not cross directories, so `lib/*.d.ts` stays in lib/.
+            let glob = GlobBuilder::new(trimmed)
+                .literal_separator(true)
+                .build()
+                .with_context(|| format!("Invalid exclude pattern: {}", pattern))?;
+            match (anchored, dir_only) {
+                (false, false) => names.add(glob),
+                (false, true) => dir_names.add(glob),
+                (true, false) => paths.add(glob),
+                (true, true) => dir_paths.add(glob),
+            };
+        }
+
+        Ok(ExcludeFilter {
+            names: names.build()?,
+            dir_names: dir_names.build()?,
+            paths: paths.build()?,
+            dir_paths: dir_paths.build()?,
+            empty: patterns.iter().all(|p| p.trim_matches('/').is_empty()),
+        })
+    }
+
+    /// The patterns `pack` stored in the `exclude` metadata key; snapshots
+    /// without the key exclude nothing.
+    pub fn from_metadata(value: Option<&str>) -> Result<Self> {
+        let patterns: Vec<String> = value
+            .and_then(|patterns| serde_json::from_str(patterns).ok())
+            .unwrap_or_default();
+        Self::new(&patterns)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.empty
+    }
+
+    /// Whether the file at `relative_path` (or one of its parent
+    /// directories) is excluded.
+    pub fn is_excluded(&self, relative_path: &str) -> bool {
+        self.matches(relative_path, false)
+    }
+
+    /// Whether the directory at `relative_path` (or one of its parents) is
+    /// excluded.
+    pub fn is_dir_excluded(&self, relative_path: &str) -> bool {
+        self.matches(relative_path, true)
+    }
+
+    fn matches(&self, relative_path: &str, is_dir: bool) -> bool {
+        let components: Vec<&str> = relative_path.split('/').collect();
+        let last = components.len() - 1;
+
+        let mut prefix_end = 0;
+        for (i, name) in components.iter().enumerate() {
+            prefix_end += name.len() + usize::from(i > 0);
+            let prefix = &relative_path[..prefix_end];
+            let dir = i < last || is_dir;
+
+            if self.names.is_match(name) || self.paths.is_match(prefix) {
+                return true;
+            }
+            if dir && (self.dir_names.is_match(name) || self.dir_paths.is_match(prefix)) {
+                return true;
+            }
+        }
+
+        false
+    }
+}
+
+/// Reads patterns from an ignore file: one per line, `#` starts a comment.
+pub fn read_ignore_file(path: &Path) -> Result<Vec<String>> {
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+
+    let content = fs::read_to_string(path)?;
+    Ok(content
+        .lines()
+        .map(|line| line.trim())
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| line.to_string())
+        .collect())
+}
+
+/// Drops excluded files and directories from `packages`, returning the
+/// number of files and bytes removed. A package's own `package.json` is
+/// always kept.
+pub fn apply_excludes(packages: &mut [ScannedPackage], filter: &ExcludeFilter) -> (usize, u64) {
+    let mut dropped_files = 0;
+    let mut dropped_size = 0;
+
+    for pkg in packages.iter_mut() {
+        pkg.files.retain(|file| {
+            if file.relative_path == "package.json" || !filter.is_excluded(&file.relative_path) {
+                return true;
+            }
+            dropped_files += 1;
+            dropped_size += file.size;
+            false
+        });
+        pkg.directories
+            .retain(|dir| !filter.is_dir_excluded(&dir.relative_path));
+    }
+
+    (dropped_files, dropped_size)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn filter(patterns: &[&str]) -> ExcludeFilter {
+        let patterns: Vec<String> = patterns.iter().map(|p| p.to_string()).collect();
+        ExcludeFilter::new(&patterns).unwrap()
+    }
+
+    #[test]
+    fn test_prune_profile() {
+        let prune = filter(&PRUNE_PATTERNS);
+        assert!(prune.is_excluded("README.md"));
+        assert!(prune.is_excluded("lib/index.js.map"));
+        assert!(prune.is_excluded("test/fixtures/a.js"));
+        assert!(prune.is_excluded("src/__tests__/a.test.js"));
+        assert!(prune.is_excluded(".github/workflows/ci.yml"));
+        assert!(prune.is_dir_excluded("docs"));
+        assert!(!prune.is_excluded("lib/index.js"));
+        assert!(!prune.is_excluded("lib/test"));
+        assert!(!prune.is_dir_excluded("lib"));
+    }
+
+    #[test]
+    fn test_anchored_patterns() {
+        let f = filter(&["lib/*.d.ts", "/dist/"]);
+        assert!(f.is_excluded("lib/index.d.ts"));
+        assert!(!f.is_excluded("src/lib/index.d.ts"));
+        assert!(f.is_excluded("dist/bundle.js"));
+        assert!(!f.is_excluded("src/dist/bundle.js"));
+        assert!(!f.is_excluded("dist"));
+    }
+
+    #[test]
+    fn test_anchored_patterns_stay_in_directory() {
+        let f = filter(&["lib/*.d.ts", "lib/**/*.map"]);
+        assert!(!f.is_excluded("lib/sub/x.d.ts"));
+        assert!(f.is_excluded("lib/sub/x.js.map"));
+        assert!(f.is_excluded("lib/x.js.map"));
+    }
+}
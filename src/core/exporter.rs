@@ -0,0 +1,305 @@
+use anyhow::{bail, Result};
+use std::collections::{HashMap, HashSet};
+use std::io::Write;
+use tar::{Builder, EntryType, Header};
+
+use crate::core::store::Store;
+use crate::types::{BinEntry, ExportSummary, ProgressFn};
+use crate::utils::compression::decompress;
+
+/// Writes a snapshot's directories, files and `.bin` links to a tar stream,
+/// decompressing one blob at a time. Entry paths are the snapshot's paths
+/// below `prefix`. Returns the writer so compressors can be finished.
+pub fn write_archive<W: Write>(
+    store: &Store,
+    writer: W,
+    prefix: &str,
+    on_progress: Option<&ProgressFn<'_>>,
+) -> Result<(W, ExportSummary)> {
+    let mut builder = Builder::new(writer);
+    let mut summary = ExportSummary::default();
+
+    let mut files = store.get_all_files()?;
+    files.sort_by_key(|f| f.node_modules_path());
+
+    // Directories have no recorded mtime; the newest file's keeps the
+    // archive reproducible.
+    let dir_mtime = files
+        .iter()
+        .map(|f| mtime_secs(f.record.mtime))
+        .max()
+        .unwrap_or(0);
+
+    let mut directories = store.get_directories(None)?;
+    directories.sort_by_key(|d| join_path(&d.package_path, &d.relative_path));
+    for dir in &directories {
+        let mut header = Header::new_gnu();
+        header.set_entry_type(EntryType::Directory);
+        header.set_mode(dir.mode & 0o7777);
+        header.set_mtime(dir_mtime);
+        header.set_size(0);
+        let path = archive_path(prefix, &join_path(&dir.package_path, &dir.relative_path));
+        builder.append_data(&mut header, path, std::io::empty())?;
+        summary.directories += 1;
+    }
+
+    let total = files.len();
+    let mut file_paths = HashSet::new();
+    for (index, file) in files.iter().enumerate() {
+        let path = file.node_modules_path();
+        if let Some(progress) = on_progress {
+            progress(index + 1, total, &file.record.relative_path);
+        }
+
+        let content = match store.get_blob(&file.record.blob_hash)? {
+            Some(compressed) => decompress(&compressed)?,
+            None => bail!("Blob missing for {}: {}", path, file.record.blob_hash),
+        };
+
+        let mut header = Header::new_gnu();
+        header.set_entry_type(EntryType::Regular);
+        header.set_mode(file.record.mode & 0o7777);
+        header.set_mtime(mtime_secs(file.record.mtime));
+        header.set_size(content.len() as u64);
+        builder.append_data(&mut header, archive_path(prefix, &path), content.as_slice())?;
+
+        summary.files += 1;
+        summary.total_size += content.len() as u64;
+        file_paths.insert(path);
+    }
+
+    let package_paths: HashMap<i64, String> = store
+        .get_packages()?
+        .into_iter()
+        .filter_map(|p| Some((p.id?, p.path)))
+        .collect();
+    let mut links = HashSet::new();
+    for record in store.get_bins(None)? {
+        let Some(package_path) = package_paths.get(&record.package_id) else {
+            continue;
+        };
+        let Some((link, target)) = bin_link(package_path, &record.bin) else {
+            continue;
+        };
+        if file_paths.contains(&link) || !links.insert(link.clone()) {
+            continue;
+        }
+
+        let mut header = Header::new_gnu();
+        header.set_entry_type(EntryType::Symlink);
+        header.set_mode(0o777);
+        header.set_mtime(dir_mtime);
+        header.set_size(0);
+        builder.append_link(&mut header, archive_path(prefix, &link), target)?;
+        summary.symlinks += 1;
+    }
+
+    let writer = builder.into_inner()?;
+    Ok((writer, summary))
+}
+
+/// The `.bin/<name>` link npm creates next to a package, and its target.
+/// None for pnpm's store paths, whose links aren't derivable from the
+/// package path alone.
+fn bin_link(package_path: &str, bin: &BinEntry) -> Option<(String, String)> {
+    if package_path.is_empty()
+        || package_path.starts_with(".pnpm/")
+        || package_path.contains("/.pnpm/")
+        || bin.name.is_empty()
+        || bin.name.contains('/')
+    {
+        return None;
+    }
+
+    let segments: Vec<&str> = package_path.split('/').collect();
+    let name_len = match segments.len() {
+        n if n >= 2 && segments[n - 2].starts_with('@') => 2,
+        _ => 1,
+    };
+    let (parent, dir_name) = segments.split_at(segments.len() - name_len);
+    if parent.last().is_some_and(|last| *last != "node_modules") {
+        return None;
+    }
+
+    let link = join_path(&parent.join("/"), &format!(".bin/{}", bin.name));
+    let target = format!("../{}/{}", dir_name.join("/"), bin.path);
+    Some((link, target))
+}
+
+fn archive_path(prefix: &str, path: &str) -> String {
+    join_path(prefix.trim_end_matches('/'), path)
+}
+
+fn join_path(dir: &str, path: &str) -> String {
+    match (dir.is_empty(), path.is_empty()) {
+        (true, _) => path.to_string(),
+        (false, true) => dir.to_string(),
+        (false, false) => format!("{}/{}", dir, path),
+    }
+}
+
+/// File mtimes are stored in milliseconds.
+fn mtime_secs(mtime_ms: i64) -> u64 {
+    (mtime_ms / 1000).max(0) as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::hasher::hash_buffer;
+    use crate::types::{BlobInfo, FileRecord, PackageInfo};
+    use crate::utils::compression::compress;
+    use std::io::Read;
+    use tar::Archive;
+
+    fn bin(name: &str, path: &str) -> BinEntry {
+        BinEntry {
+            name: name.to_string(),
+            path: path.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_bin_link() {
+        assert_eq!(
+            bin_link("typescript", &bin("tsc", "bin/tsc")),
+            Some((".bin/tsc".to_string(), "../typescript/bin/tsc".to_string()))
+        );
+        assert_eq!(
+            bin_link(
+                "packages/app/node_modules/@scope/cli",
+                &bin("cli", "cli.js")
+            ),
+            Some((
+                "packages/app/node_modules/.bin/cli".to_string(),
+                "../@scope/cli/cli.js".to_string()
+            ))
+        );
+        assert_eq!(
+            bin_link(
+                ".pnpm/typescript@5.0.0/node_modules/typescript",
+                &bin("tsc", "bin/tsc")
+            ),
+            None
+        );
+        assert_eq!(bin_link("packages/app", &bin("app", "index.js")), None);
+    }
+
+    #[test]
+    fn test_write_archive() {
+        let dir = tempfile::tempdir().unwrap();
+        let db_path = dir.path().join("test.db");
+        let mut store = Store::open(db_path.to_str().unwrap()).unwrap();
+
+        let package_id = store
+            .insert_package(&PackageInfo {
+                id: None,
+                name: "cli".to_string(),
+                version: "1.0.0".to_string(),
+                path: "cli".to_string(),
+            })
+            .unwrap();
+        let content = b"#!/usr/bin/env node";
+        let compressed = compress(content, 6);
+        store
+            .insert_blob(&BlobInfo {
+                hash: hash_buffer(content),
+                content: compressed.clone(),
+                original_size: content.len() as u64,
+                compressed_size: compressed.len() as u64,
+            })
+            .unwrap();
+        store
+            .insert_file(&FileRecord {
+                id: None,
+                package_id,
+                relative_path: "bin/cli.js".to_string(),
+                blob_hash: hash_buffer(content),
+                mode: 0o100755,
+                mtime: 1_700_000_000_500,
+            })
+            .unwrap();
+        store
+            .transaction(|tx| {
+                tx.execute(
+                    "INSERT INTO directories (package_id, relative_path, mode) VALUES (?1, 'bin', ?2)",
+                    rusqlite::params![package_id, 0o40750],
+                )?;
+                tx.execute(
+                    "INSERT INTO bins (package_id, name, path) VALUES (?1, 'cli', 'bin/cli.js')",
+                    rusqlite::params![package_id],
+                )?;
+                Ok(())
+            })
+            .unwrap();
+
+        let (archive, summary) = write_archive(&store, Vec::new(), "node_modules", None).unwrap();
+        assert_eq!(
+            (summary.files, summary.directories, summary.symlinks),
+            (1, 1, 1)
+        );
+
+        let mut entries = Vec::new();
+        for entry in Archive::new(archive.as_slice()).entries().unwrap() {
+            let mut entry = entry.unwrap();
+            let header = entry.header().clone();
+            let link = entry
+                .link_name()
+                .unwrap()
+                .map(|l| l.to_string_lossy().to_string());
+            let mut data = Vec::new();
+            entry.read_to_end(&mut data).unwrap();
+            entries.push((
+                entry.path().unwrap().to_string_lossy().to_string(),
+                header.entry_type(),
+                header.mode().unwrap(),
+                header.mtime().unwrap(),
+                link,
+                data,
+            ));
+        }
+        assert_eq!(
+            entries,
+            vec![
+                (
+                    "node_modules/cli/bin".to_string(),
+                    EntryType::Directory,
+                    0o750,
+                    1_700_000_000,
+                    None,
+                    Vec::new()
+                ),
+                (
+                    "node_modules/cli/bin/cli.js".to_string(),
+                    EntryType::Regular,
+                    0o755,
+                    1_700_000_000,
+                    None,
+                    content.to_vec()
+                ),
+                (
+                    "node_modules/.bin/cli".to_string(),
+                    EntryType::Symlink,
+                    0o777,
+                    1_700_000_000,
+                    Some("../cli/bin/cli.js".to_string()),
+                    Vec::new()
+                ),
+            ]
+        );
+
+        // A file whose blob is gone can't be exported.
+        let conn = rusqlite::Connection::open(&db_path).unwrap();
+        conn.pragma_update(None, "foreign_keys", "OFF").unwrap();
+        conn.execute(
+            "INSERT INTO files (package_id, relative_path, blob_hash, mode, mtime)
+             VALUES (?1, 'index.js', 'missing', 420, 0)",
+            rusqlite::params![package_id],
+        )
+        .unwrap();
+        let error = write_archive(&store, Vec::new(), "", None).unwrap_err();
+        assert!(error
+            .to_string()
+            .starts_with("Blob missing for cli/index.js"));
+    }
+}
@@ -1,22 +1,33 @@
 use anyhow::Result;
+use filetime::FileTime;
 use rayon::prelude::*;
 use std::collections::HashMap;
 use std::fs;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
 
+use crate::core::journal::Journal;
 use crate::core::store::Store;
+use crate::types::{DirectoryRecord, ExtractOptions, FileRecordWithPath, LinkMode, ProgressFn};
 use crate::utils::compression::decompress;
 
-struct ExtractedFile {
-    full_path: String,
-    content: Vec<u8>,
+struct ExtractTarget {
+    id: Option<i64>,
+    full_path: PathBuf,
     mode: u32,
+    mtime: i64,
+}
+
+/// A decompressed blob and every path it is restored to.
+struct BlobGroup {
+    content: Vec<u8>,
+    targets: Vec<ExtractTarget>,
 }
 
 pub fn extract_files(
     store: &Store,
     output_path: &Path,
-    on_progress: Option<&dyn Fn(usize, usize, &str)>,
+    on_progress: Option<&ProgressFn<'_>>,
 ) -> Result<(usize, u64)> {
     let files = store.get_all_files()?;
     let total_files = files.len();
@@ -28,11 +39,10 @@ pub fn extract_files(
     for (index, file) in files.iter().enumerate() {
         if let Some(progress) = on_progress {
             let display = &file.record.relative_path;
-            let display = if display.len() > 40 {
-                &display[..40]
-            } else {
-                display
-            };
+            let display = display
+                .char_indices()
+                .nth(40)
+                .map_or(display.as_str(), |(i, _)| &display[..i]);
             progress(index + 1, total_files, display);
         }
 
@@ -83,81 +93,77 @@ pub fn extract_files(
 
 pub fn extract_files_parallel(
     store: &Store,
+    files: &[FileRecordWithPath],
     output_path: &Path,
-    on_progress: Option<&dyn Fn(usize, usize, &str)>,
+    options: &ExtractOptions,
+    journal: Option<&Journal>,
+    on_progress: Option<&ProgressFn<'_>>,
 ) -> Result<(usize, u64)> {
-    let files = store.get_all_files()?;
     let total_files = files.len();
 
     if let Some(progress) = on_progress {
         progress(0, total_files, "Reading blobs...");
     }
 
-    let mut prepared: Vec<ExtractedFile> = Vec::with_capacity(total_files);
-    let mut blob_cache: HashMap<String, Vec<u8>> = HashMap::new();
+    let mut groups: Vec<BlobGroup> = Vec::new();
+    let mut group_index: HashMap<&str, usize> = HashMap::new();
 
-    for file in &files {
-        let content = if let Some(cached) = blob_cache.get(&file.record.blob_hash) {
-            cached.clone()
-        } else {
-            let compressed = match store.get_blob(&file.record.blob_hash)? {
-                Some(data) => data,
-                None => {
-                    eprintln!("Blob not found: {}", file.record.relative_path);
-                    continue;
-                }
-            };
-            let decompressed = decompress(&compressed)?;
+    for file in files {
+        let full_path = Path::new(output_path)
+            .join(&file.package_path)
+            .join(&file.record.relative_path);
+        let target = ExtractTarget {
+            id: file.record.id,
+            full_path,
+            mode: file.record.mode,
+            mtime: file.record.mtime,
+        };
 
-            if decompressed.len() < 100 * 1024 {
-                blob_cache.insert(file.record.blob_hash.clone(), decompressed.clone());
-            }
+        if let Some(&index) = group_index.get(file.record.blob_hash.as_str()) {
+            groups[index].targets.push(target);
+            continue;
+        }
 
-            decompressed
+        let compressed = match store.get_blob(&file.record.blob_hash)? {
+            Some(data) => data,
+            None => {
+                eprintln!("Blob not found: {}", file.record.relative_path);
+                continue;
+            }
         };
+        let content = decompress(&compressed)?;
 
-        let full_path = Path::new(output_path)
-            .join(&file.package_path)
-            .join(&file.record.relative_path)
-            .to_string_lossy()
-            .to_string();
-
-        prepared.push(ExtractedFile {
-            full_path,
+        group_index.insert(&file.record.blob_hash, groups.len());
+        groups.push(BlobGroup {
             content,
-            mode: file.record.mode,
+            targets: vec![target],
         });
     }
 
-    drop(blob_cache);
-
     if let Some(progress) = on_progress {
         progress(total_files / 2, total_files, "Writing files...");
     }
 
-    let total_size: u64 = prepared
+    let link_supported = AtomicBool::new(true);
+
+    let sizes: Vec<u64> = groups
         .par_iter()
-        .map(|ef| {
-            let path = Path::new(&ef.full_path);
-            if let Some(parent) = path.parent() {
-                let _ = fs::create_dir_all(parent);
+        .map(|group| {
+            let size = write_group(group, options, &link_supported)?;
+            // Only once every target is written, so a failed group is
+            // rewritten on resume.
+            if let Some(journal) = journal {
+                let ids: Vec<i64> = group.targets.iter().filter_map(|t| t.id).collect();
+                journal.record(&ids)?;
             }
-            let _ = fs::write(path, &ef.content);
-
-            #[cfg(unix)]
-            {
-                use std::os::unix::fs::PermissionsExt;
-                if ef.mode != 0 {
-                    let _ = fs::set_permissions(
-                        path,
-                        fs::Permissions::from_mode(ef.mode & 0o777),
-                    );
-                }
-            }
-
-            ef.content.len() as u64
+            Ok(size)
         })
-        .sum();
+        .collect::<Result<_>>()?;
+    let total_size: u64 = sizes.iter().sum();
+
+    if options.link_mode != LinkMode::Copy && !link_supported.load(Ordering::Relaxed) {
+        eprintln!("Warning: linking is not supported here, some files were copied instead");
+    }
 
     if let Some(progress) = on_progress {
         progress(total_files, total_files, "Done");
@@ -165,3 +171,259 @@ pub fn extract_files_parallel(
 
     Ok((total_files, total_size))
 }
+
+/// Creates recorded directories (including empty ones) and applies their
+/// modes. Runs after files are written so restrictive modes don't block it.
+pub fn restore_directories(directories: &[DirectoryRecord], output_path: &Path) -> Result<usize> {
+    let mut paths: Vec<(PathBuf, u32)> = directories
+        .iter()
+        .map(|dir| {
+            let path = output_path.join(&dir.package_path).join(&dir.relative_path);
+            (path, dir.mode)
+        })
+        .collect();
+
+    for (path, _) in &paths {
+        fs::create_dir_all(path)?;
+    }
+
+    // Deepest first, so a parent losing its write/search bit comes last.
+    paths.sort_by_key(|(path, _)| std::cmp::Reverse(path.components().count()));
+
+    #[cfg(unix)]
+    for (path, mode) in &paths {
+        use std::os::unix::fs::PermissionsExt;
+        if *mode != 0 {
+            fs::set_permissions(path, fs::Permissions::from_mode(mode & 0o777))?;
+        }
+    }
+
+    Ok(paths.len())
+}
+
+/// Writes a blob to every target, linking targets to the first copy when
+/// the link mode allows it. Returns the bytes written, so linked targets
+/// don't count again.
+fn write_group(
+    group: &BlobGroup,
+    options: &ExtractOptions,
+    link_supported: &AtomicBool,
+) -> Result<u64> {
+    let link_mode = options.link_mode;
+    // Hardlinks share permissions and mtime, so only link files that
+    // share both.
+    let mut sources: HashMap<(u32, Option<i64>), &Path> = HashMap::new();
+    let mut written: u64 = 0;
+
+    for target in &group.targets {
+        let path = target.full_path.as_path();
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        let mtime = options
+            .fixed_mtime
+            .or((target.mtime > 0).then_some(target.mtime))
+            .filter(|_| options.restore_mtime);
+
+        // Replace rather than overwrite: the existing file may be a
+        // hardlink (pnpm's store, an earlier hardlink unpack) whose other
+        // paths must keep their content.
+        if let Err(e) = fs::remove_file(path) {
+            if e.kind() != std::io::ErrorKind::NotFound {
+                return Err(e.into());
+            }
+        }
+
+        let source_key = match link_mode {
+            LinkMode::Hardlink => (target.mode, mtime),
+            _ => (0, None),
+        };
+        let linked = match (link_mode, sources.get(&source_key)) {
+            (LinkMode::Copy, _) | (_, None) => false,
+            (_, Some(_)) if !link_supported.load(Ordering::Relaxed) => false,
+            (mode, Some(source)) => {
+                let result = if mode == LinkMode::Hardlink {
+                    fs::hard_link(source, path)
+                } else {
+                    reflink_copy::reflink(source, path)
+                };
+                // Unsupported here (another filesystem, no CoW): copy instead.
+                if result.is_err() {
+                    link_supported.store(false, Ordering::Relaxed);
+                }
+                result.is_ok()
+            }
+        };
+
+        if !linked {
+            fs::write(path, &group.content)?;
+            written += group.content.len() as u64;
+        }
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            if target.mode != 0 {
+                fs::set_permissions(path, fs::Permissions::from_mode(target.mode & 0o777))?;
+            }
+        }
+
+        if let Some(mtime) = mtime {
+            filetime::set_file_mtime(path, to_file_time(mtime))?;
+        }
+
+        sources.entry(source_key).or_insert(path);
+    }
+
+    Ok(written)
+}
+
+/// Converts a stored mtime (milliseconds since the epoch) to a `FileTime`.
+fn to_file_time(mtime_ms: i64) -> FileTime {
+    FileTime::from_unix_time(
+        mtime_ms.div_euclid(1000),
+        (mtime_ms.rem_euclid(1000) * 1_000_000) as u32,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn group(dir: &Path, targets: &[(&str, u32)]) -> BlobGroup {
+        BlobGroup {
+            content: b"MIT".to_vec(),
+            targets: targets
+                .iter()
+                .map(|(name, mode)| ExtractTarget {
+                    id: None,
+                    full_path: dir.join(name),
+                    mode: *mode,
+                    mtime: 1_700_000_000_123,
+                })
+                .collect(),
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_write_group_replaces_hardlinked_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let group = group(dir.path(), &[("a/LICENSE", 0o644)]);
+        fs::create_dir_all(dir.path().join("a")).unwrap();
+        fs::write(dir.path().join("store"), "old").unwrap();
+        fs::hard_link(dir.path().join("store"), dir.path().join("a/LICENSE")).unwrap();
+
+        write_group(&group, &ExtractOptions::default(), &AtomicBool::new(true)).unwrap();
+
+        assert_eq!(fs::read(dir.path().join("a/LICENSE")).unwrap(), b"MIT");
+        assert_eq!(fs::read(dir.path().join("store")).unwrap(), b"old");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_write_group_hardlinks_same_mode_only() {
+        use std::os::unix::fs::MetadataExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let group = group(
+            dir.path(),
+            &[
+                ("a/LICENSE", 0o644),
+                ("b/LICENSE", 0o644),
+                ("c/LICENSE", 0o755),
+            ],
+        );
+        let supported = AtomicBool::new(true);
+
+        let options = ExtractOptions {
+            link_mode: LinkMode::Hardlink,
+            ..Default::default()
+        };
+        // b/LICENSE is linked, so only two copies are written.
+        let size = write_group(&group, &options, &supported).unwrap();
+        assert_eq!(size, 6);
+
+        let ino = |p: &str| fs::metadata(dir.path().join(p)).unwrap().ino();
+        assert_eq!(ino("a/LICENSE"), ino("b/LICENSE"));
+        assert_ne!(ino("a/LICENSE"), ino("c/LICENSE"));
+        assert_eq!(fs::read(dir.path().join("c/LICENSE")).unwrap(), b"MIT");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_write_group_hardlinks_same_mtime_only() {
+        use std::os::unix::fs::MetadataExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let mut group = group(dir.path(), &[("a/x", 0o644), ("b/x", 0o644)]);
+        group.targets[1].mtime = 1_600_000_000_000;
+        let supported = AtomicBool::new(true);
+
+        let options = ExtractOptions {
+            link_mode: LinkMode::Hardlink,
+            restore_mtime: true,
+            ..Default::default()
+        };
+        write_group(&group, &options, &supported).unwrap();
+
+        let metadata = |p: &str| fs::metadata(dir.path().join(p)).unwrap();
+        assert_ne!(metadata("a/x").ino(), metadata("b/x").ino());
+        assert_eq!(metadata("a/x").mtime(), 1_700_000_000);
+        assert_eq!(metadata("b/x").mtime(), 1_600_000_000);
+    }
+
+    #[test]
+    fn test_write_group_copy() {
+        let dir = tempfile::tempdir().unwrap();
+        let group = group(dir.path(), &[("a/x", 0), ("b/x", 0)]);
+        let supported = AtomicBool::new(true);
+
+        let size = write_group(&group, &ExtractOptions::default(), &supported).unwrap();
+
+        assert_eq!(size, 6);
+        assert_eq!(fs::read(dir.path().join("a/x")).unwrap(), b"MIT");
+        assert_eq!(fs::read(dir.path().join("b/x")).unwrap(), b"MIT");
+    }
+
+    #[test]
+    fn test_write_group_restores_mtime() {
+        let dir = tempfile::tempdir().unwrap();
+        let group = group(dir.path(), &[("a/x", 0)]);
+        let supported = AtomicBool::new(true);
+
+        write_group(&group, &ExtractOptions::default(), &supported).unwrap();
+
+        let metadata = fs::metadata(dir.path().join("a/x")).unwrap();
+        let mtime = FileTime::from_last_modification_time(&metadata);
+        assert_eq!(mtime.unix_seconds(), 1_700_000_000);
+        assert_eq!(mtime.nanoseconds(), 123_000_000);
+    }
+
+    #[test]
+    fn test_write_group_reports_errors() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("a"), "not a directory").unwrap();
+        let group = group(dir.path(), &[("a/x", 0)]);
+        let supported = AtomicBool::new(true);
+
+        assert!(write_group(&group, &ExtractOptions::default(), &supported).is_err());
+    }
+
+    #[test]
+    fn test_restore_directories_creates_empty_dirs() {
+        let dir = tempfile::tempdir().unwrap();
+        let directories = vec![DirectoryRecord {
+            package_id: 1,
+            package_path: "pkg".to_string(),
+            relative_path: "dist/cache".to_string(),
+            mode: 0o755,
+        }];
+
+        let count = restore_directories(&directories, dir.path()).unwrap();
+
+        assert_eq!(count, 1);
+        assert!(dir.path().join("pkg/dist/cache").is_dir());
+    }
+}
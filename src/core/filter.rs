@@ -0,0 +1,196 @@
+use anyhow::{Context, Result};
+use globset::{Glob, GlobMatcher};
+use semver::{Version, VersionReq};
+
+use crate::types::PackageInfo;
+
+/// A single `--package` selector: `<name>[@<range>]`, where the name may be
+/// a glob matched against the package name or its path inside node_modules
+/// (e.g. `@babel/*`, `lodash@^4`, `.pnpm/lodash@*`).
+#[derive(Debug, Clone)]
+struct PackageSpec {
+    whole: GlobMatcher,
+    name: Option<GlobMatcher>,
+    range: Option<String>,
+}
+
+impl PackageSpec {
+    fn parse(spec: &str) -> Result<Self> {
+        let whole = Glob::new(spec)
+            .with_context(|| format!("Invalid package filter: {}", spec))?
+            .compile_matcher();
+
+        let (name, range) = match spec.rfind('@') {
+            Some(at) if at > 0 && !spec[at + 1..].contains('/') => {
+                let name = Glob::new(&spec[..at])
+                    .with_context(|| format!("Invalid package filter: {}", spec))?
+                    .compile_matcher();
+                (Some(name), Some(spec[at + 1..].to_string()))
+            }
+            _ => (None, None),
+        };
+
+        Ok(PackageSpec { whole, name, range })
+    }
+
+    fn matches(&self, pkg: &PackageInfo) -> bool {
+        if self.whole.is_match(&pkg.name) || self.whole.is_match(&pkg.path) {
+            return true;
+        }
+
+        match (&self.name, &self.range) {
+            (Some(name), Some(range)) => {
+                (name.is_match(&pkg.name) || name.is_match(&pkg.path))
+                    && version_satisfies(&pkg.version, range)
+            }
+            _ => false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct PackageFilter {
+    specs: Vec<PackageSpec>,
+}
+
+impl PackageFilter {
+    pub fn new(specs: &[String]) -> Result<Self> {
+        let specs = specs
+            .iter()
+            .map(|s| PackageSpec::parse(s))
+            .collect::<Result<Vec<_>>>()?;
+        Ok(PackageFilter { specs })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.specs.is_empty()
+    }
+
+    pub fn matches(&self, pkg: &PackageInfo) -> bool {
+        self.specs.is_empty() || self.specs.iter().any(|s| s.matches(pkg))
+    }
+}
+
+/// Checks `version` against an npm-style range (`^1.2`, `~1.2.3`, `1.x`,
+/// `>=1 <2`, `1.0.0 - 2.0.0`, `a || b`).
+pub fn version_satisfies(version: &str, range: &str) -> bool {
+    let Ok(version) = Version::parse(version.trim_start_matches('v')) else {
+        return false;
+    };
+
+    range.split("||").any(|alt| {
+        to_version_req(alt)
+            .map(|req| req.matches(&version))
+            .unwrap_or(false)
+    })
+}
+
+/// Whether `range` is a semver range rather than a tag, URL or protocol
+/// specifier (`next`, `github:user/repo`, `workspace:*`).
+pub fn is_version_range(range: &str) -> bool {
+    range.split("||").all(|alt| to_version_req(alt).is_some())
+}
+
+fn to_version_req(range: &str) -> Option<VersionReq> {
+    let range = range.trim();
+    if range.is_empty() || range == "*" || range == "latest" {
+        return Some(VersionReq::STAR);
+    }
+
+    let tokens: Vec<&str> = range.split_whitespace().collect();
+    if let [from, "-", to] = tokens.as_slice() {
+        return VersionReq::parse(&format!(">={}, <={}", from, to)).ok();
+    }
+
+    let mut comparators: Vec<String> = Vec::new();
+    let mut pending_op = String::new();
+    for token in tokens {
+        if token.chars().all(|c| "<>=~^".contains(c)) {
+            pending_op.push_str(token);
+            continue;
+        }
+
+        let token = format!("{}{}", pending_op, token.trim_start_matches('v'));
+        pending_op.clear();
+
+        let is_bare = token.starts_with(|c: char| c.is_ascii_digit());
+        let has_wildcard = token.contains(['x', 'X', '*']);
+        if is_bare && !has_wildcard {
+            comparators.push(format!("={}", token));
+        } else {
+            comparators.push(token);
+        }
+    }
+
+    VersionReq::parse(&comparators.join(", ")).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pkg(name: &str, version: &str, path: &str) -> PackageInfo {
+        PackageInfo {
+            id: None,
+            name: name.to_string(),
+            version: version.to_string(),
+            path: path.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_version_satisfies() {
+        assert!(version_satisfies("4.17.21", "^4.0.0"));
+        assert!(version_satisfies("4.17.21", "4.x"));
+        assert!(version_satisfies("4.17.21", "4.17.21"));
+        assert!(!version_satisfies("4.17.20", "4.17.21"));
+        assert!(version_satisfies("1.2.9", "1.2"));
+        assert!(!version_satisfies("1.3.0", "1.2"));
+        assert!(version_satisfies("1.5.0", ">=1.0.0 <2.0.0"));
+        assert!(version_satisfies("1.5.0", ">= 1.0.0 < 2.0.0"));
+        assert!(version_satisfies("2.0.0", "1.0.0 - 2.0.0"));
+        assert!(version_satisfies("3.1.0", "^1 || ^3"));
+        assert!(!version_satisfies("2.0.0", "^1 || ^3"));
+        assert!(version_satisfies("0.0.1", "*"));
+        assert!(!version_satisfies("not-a-version", "*"));
+        assert!(is_version_range("^1 || 2.x"));
+        assert!(!is_version_range("github:user/repo"));
+        assert!(!is_version_range("workspace:*"));
+    }
+
+    #[test]
+    fn test_package_filter_name_and_range() {
+        let filter = PackageFilter::new(&["lodash@^4".to_string()]).unwrap();
+        assert!(filter.matches(&pkg("lodash", "4.17.21", "lodash")));
+        assert!(!filter.matches(&pkg("lodash", "3.10.1", "lodash")));
+        assert!(!filter.matches(&pkg("lodash-es", "4.17.21", "lodash-es")));
+    }
+
+    #[test]
+    fn test_package_filter_scoped_and_glob() {
+        let filter = PackageFilter::new(&["@babel/*".to_string()]).unwrap();
+        assert!(filter.matches(&pkg("@babel/core", "7.0.0", "@babel/core")));
+        assert!(!filter.matches(&pkg("babel", "7.0.0", "babel")));
+
+        let filter = PackageFilter::new(&["@babel/core@7".to_string()]).unwrap();
+        assert!(filter.matches(&pkg("@babel/core", "7.24.0", "@babel/core")));
+        assert!(!filter.matches(&pkg("@babel/core", "6.0.0", "@babel/core")));
+    }
+
+    #[test]
+    fn test_package_filter_pnpm_path() {
+        let path = ".pnpm/lodash@4.17.21/node_modules/lodash";
+        let filter = PackageFilter::new(&[".pnpm/lodash@*".to_string()]).unwrap();
+        assert!(filter.matches(&pkg("lodash", "4.17.21", path)));
+
+        let filter = PackageFilter::new(&["lodash".to_string()]).unwrap();
+        assert!(filter.matches(&pkg("lodash", "4.17.21", path)));
+    }
+
+    #[test]
+    fn test_empty_filter_matches_everything() {
+        let filter = PackageFilter::new(&[]).unwrap();
+        assert!(filter.is_empty());
+        assert!(filter.matches(&pkg("anything", "1.0.0", "anything")));
+    }
+}
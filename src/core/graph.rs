@@ -0,0 +1,238 @@
+use anyhow::Result;
+use semver::Version;
+use std::collections::HashMap;
+
+use crate::core::filter::{is_version_range, version_satisfies};
+use crate::core::scanner::ROOT_PACKAGE_NAME;
+use crate::core::store::Store;
+use crate::types::{Dependency, DependencyRecord, Layout, PackageInfo, RootDependency};
+
+/// Who declared a dependency: a recorded package (by index) or one of the
+/// project's own package.json files (by importer directory).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Dependent {
+    Package(usize),
+    Importer(String),
+}
+
+/// The snapshot's dependency relations, resolved to recorded packages.
+pub struct DependencyGraph {
+    layout: Layout,
+    packages: Vec<PackageInfo>,
+    by_path: HashMap<String, usize>,
+    by_name: HashMap<String, Vec<usize>>,
+    dependents: HashMap<usize, Vec<(Dependent, Dependency)>>,
+}
+
+impl DependencyGraph {
+    pub fn load(store: &Store) -> Result<Self> {
+        let layout = Layout::from_metadata(store.get_metadata("layout")?.as_deref());
+        Ok(Self::new(
+            layout,
+            store.get_packages()?,
+            store.get_dependencies(None)?,
+            store.get_root_dependencies()?,
+        ))
+    }
+
+    pub fn new(
+        layout: Layout,
+        packages: Vec<PackageInfo>,
+        dependencies: Vec<DependencyRecord>,
+        root_dependencies: Vec<RootDependency>,
+    ) -> Self {
+        let packages: Vec<PackageInfo> = packages
+            .into_iter()
+            .filter(|p| p.name != ROOT_PACKAGE_NAME)
+            .collect();
+
+        let mut by_path = HashMap::new();
+        let mut by_name: HashMap<String, Vec<usize>> = HashMap::new();
+        let mut by_id = HashMap::new();
+        for (index, pkg) in packages.iter().enumerate() {
+            by_path.insert(pkg.path.clone(), index);
+            by_name.entry(pkg.name.clone()).or_default().push(index);
+            if let Some(id) = pkg.id {
+                by_id.insert(id, index);
+            }
+        }
+
+        let mut graph = DependencyGraph {
+            layout,
+            packages,
+            by_path,
+            by_name,
+            dependents: HashMap::new(),
+        };
+
+        for record in dependencies {
+            let Some(&from) = by_id.get(&record.package_id) else {
+                continue;
+            };
+            let from_dir = graph.packages[from].path.clone();
+            if let Some(to) = graph.resolve(&from_dir, &record.dependency) {
+                graph
+                    .dependents
+                    .entry(to)
+                    .or_default()
+                    .push((Dependent::Package(from), record.dependency));
+            }
+        }
+
+        for root in root_dependencies {
+            if let Some(to) = graph.resolve(&root.importer, &root.dependency) {
+                graph
+                    .dependents
+                    .entry(to)
+                    .or_default()
+                    .push((Dependent::Importer(root.importer), root.dependency));
+            }
+        }
+
+        graph
+    }
+
+    pub fn packages(&self) -> &[PackageInfo] {
+        &self.packages
+    }
+
+    /// Declarations that resolve to the package at `index`.
+    pub fn dependents(&self, index: usize) -> &[(Dependent, Dependency)] {
+        self.dependents.get(&index).map_or(&[], |d| d.as_slice())
+    }
+
+    /// Finds the package a dependency declared in `from_dir` resolves to.
+    ///
+    /// Follows Node's lookup through the recorded package paths first. When
+    /// nothing is recorded there (pnpm's symlinked layout, Yarn PnP), falls
+    /// back to the highest recorded version satisfying the range. A match
+    /// outside a semver range means the real copy is one that isn't recorded
+    /// as a package (nested inside another package's files), so it's skipped.
+    pub fn resolve(&self, from_dir: &str, dependency: &Dependency) -> Option<usize> {
+        // `npm:<name>@<range>` installs another package under this name.
+        let (name, range) = match dependency.spec.strip_prefix("npm:") {
+            Some(alias) => match alias.rfind('@') {
+                Some(at) if at > 0 => (&alias[..at], &alias[at + 1..]),
+                _ => (alias, "*"),
+            },
+            None => (dependency.name.as_str(), dependency.spec.as_str()),
+        };
+        let is_range = is_version_range(range);
+        let accepts =
+            |index: usize| !is_range || version_satisfies(&self.packages[index].version, range);
+
+        let found = self
+            .lookup_paths(from_dir, &dependency.name)
+            .iter()
+            .find_map(|candidate| self.by_path.get(candidate).copied());
+        if let Some(index) = found.filter(|&i| accepts(i)) {
+            return Some(index);
+        }
+
+        self.by_name
+            .get(name)?
+            .iter()
+            .copied()
+            .filter(|&i| accepts(i))
+            .max_by_key(|&i| Version::parse(&self.packages[i].version).ok())
+    }
+
+    /// `<dir>/node_modules/<name>` for `from_dir` and each of its ancestors,
+    /// in the path space of the snapshot's package paths.
+    fn lookup_paths(&self, from_dir: &str, name: &str) -> Vec<String> {
+        let mut paths = Vec::new();
+        let mut dir = Some(from_dir);
+
+        while let Some(current) = dir {
+            let is_node_modules = current.rsplit('/').next() == Some("node_modules");
+            if !is_node_modules {
+                paths.push(match (current.is_empty(), self.layout) {
+                    // Node_modules-rooted snapshots store top-level packages
+                    // without the node_modules prefix.
+                    (true, Layout::NodeModules) => name.to_string(),
+                    (true, _) => format!("node_modules/{}", name),
+                    (false, _) => format!("{}/node_modules/{}", current, name),
+                });
+            }
+
+            dir = if current.is_empty() {
+                None
+            } else {
+                Some(current.rsplit_once('/').map_or("", |(parent, _)| parent))
+            };
+        }
+
+        paths
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::DependencyKind;
+
+    fn pkg(id: i64, name: &str, version: &str, path: &str) -> PackageInfo {
+        PackageInfo {
+            id: Some(id),
+            name: name.to_string(),
+            version: version.to_string(),
+            path: path.to_string(),
+        }
+    }
+
+    fn dep(package_id: i64, name: &str, spec: &str) -> DependencyRecord {
+        DependencyRecord {
+            package_id,
+            dependency: Dependency {
+                name: name.to_string(),
+                spec: spec.to_string(),
+                kind: DependencyKind::Dependencies,
+            },
+        }
+    }
+
+    #[test]
+    fn test_resolve_nested_before_hoisted() {
+        let graph = DependencyGraph::new(
+            Layout::Workspace,
+            vec![
+                pkg(1, "debug", "4.3.4", "node_modules/debug"),
+                pkg(2, "debug", "2.6.9", "packages/app/node_modules/debug"),
+                pkg(3, "express", "4.18.2", "packages/app/node_modules/express"),
+                pkg(4, "ms", "2.1.2", "node_modules/ms"),
+            ],
+            vec![dep(3, "debug", "2.6.9"), dep(1, "ms", "2.1.2")],
+            vec![RootDependency {
+                importer: String::new(),
+                dependency: dep(0, "debug", "^4").dependency,
+            }],
+        );
+
+        assert_eq!(
+            graph.dependents(1),
+            &[(Dependent::Package(2), dep(3, "debug", "2.6.9").dependency)]
+        );
+        assert_eq!(graph.dependents(0)[0].0, Dependent::Importer(String::new()));
+        assert_eq!(graph.dependents(3)[0].0, Dependent::Package(0));
+    }
+
+    #[test]
+    fn test_resolve_pnpm_by_range_and_alias() {
+        let graph = DependencyGraph::new(
+            Layout::NodeModules,
+            vec![
+                pkg(1, "a", "1.0.0", ".pnpm/a@1.0.0/node_modules/a"),
+                pkg(2, "b", "1.2.0", ".pnpm/b@1.2.0/node_modules/b"),
+                pkg(3, "b", "2.0.0", ".pnpm/b@2.0.0/node_modules/b"),
+            ],
+            vec![dep(1, "b", "^1.0.0"), dep(1, "c", "npm:b@^2")],
+            Vec::new(),
+        );
+
+        let from: Vec<usize> = (0..3)
+            .filter(|&i| !graph.dependents(i).is_empty())
+            .collect();
+        assert_eq!(from, vec![1, 2]);
+        assert_eq!(graph.dependents(2)[0].1.name, "c");
+    }
+}
@@ -0,0 +1,548 @@
+use anyhow::Result;
+use flate2::bufread::GzDecoder;
+use rayon::prelude::*;
+use rusqlite::params;
+use std::cell::Cell;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read};
+use std::path::{Component, Path, PathBuf};
+use std::rc::Rc;
+use tar::{Archive, EntryType};
+
+use crate::core::hasher::{hash_buffer, hash_string};
+use crate::core::scanner::{
+    pnp_cache_package, project_dependencies, root_package, scanned_package, ScanResult,
+    ScannedPackage, PNP_CACHE_DIR, PNP_ROOT_FILES, PNP_UNPLUGGED_DIR,
+};
+use crate::core::store::Store;
+use crate::types::{Dependency, DirectoryEntry, FileEntry, Layout, ProgressFn};
+use crate::utils::compression::compress;
+
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+
+/// Buffered file contents are hashed and compressed in parallel once they
+/// reach this size.
+const BATCH_SIZE: usize = 32 * 1024 * 1024;
+
+/// Mode bits `pack` records on top of the permissions tar keeps.
+const S_IFREG: u32 = 0o100000;
+const S_IFDIR: u32 = 0o040000;
+
+/// An archive read into a store: blobs are written, packages are not.
+pub struct ImportedArchive {
+    /// Packages as `pack` would have scanned them after extracting the
+    /// archive. A file's `absolute_path` is its path in the archive.
+    pub scan_result: ScanResult,
+    /// Blob hash of every file, keyed by its path in the archive.
+    pub hashes: HashMap<PathBuf, String>,
+    pub root_dependencies: Vec<Dependency>,
+    /// Hash of the lockfile `pack --include-lockfile` would record.
+    pub lockfile_hash: Option<String>,
+    pub deduplicated: usize,
+}
+
+/// A regular file read from the archive.
+struct ArchiveFile {
+    path: String,
+    hash: String,
+    size: u64,
+    mode: u32,
+    mtime: i64,
+}
+
+/// Streams a tar, tar.gz or tar.zst archive (told apart by magic bytes)
+/// into `store`: every regular file is hashed and compressed straight into
+/// the blobs table, and only paths, modes and package.json contents are
+/// kept to group files into packages afterwards. Nothing touches the disk
+/// but the DB.
+pub fn import_archive(
+    archive_path: &Path,
+    store: &mut Store,
+    compression_level: u32,
+    on_progress: Option<&ProgressFn<'_>>,
+) -> Result<ImportedArchive> {
+    let file = File::open(archive_path)?;
+    let total = file.metadata()?.len() as usize;
+    let read = Rc::new(Cell::new(0));
+    let mut archive = Archive::new(open_archive(CountingReader {
+        inner: file,
+        read: Rc::clone(&read),
+    })?);
+
+    let mut files: Vec<ArchiveFile> = Vec::new();
+    let mut file_indices: HashMap<String, usize> = HashMap::new();
+    let mut directories: Vec<(String, u32)> = Vec::new();
+    let mut manifests: HashMap<String, String> = HashMap::new();
+    let mut seen_hashes: HashSet<String> = HashSet::new();
+    let mut deduplicated = 0;
+
+    store.transaction(|tx| {
+        let mut insert_blob_stmt = tx.prepare_cached(
+            "INSERT OR IGNORE INTO blobs (hash, content, original_size, compressed_size)
+             VALUES (?1, ?2, ?3, ?4)",
+        )?;
+
+        let mut batch: Vec<(ArchiveFile, Vec<u8>)> = Vec::new();
+        let mut batch_size = 0;
+        let mut flush = |batch: &mut Vec<(ArchiveFile, Vec<u8>)>,
+                         files: &mut Vec<ArchiveFile>,
+                         file_indices: &mut HashMap<String, usize>|
+         -> Result<usize> {
+            let processed: Vec<(ArchiveFile, Option<Vec<u8>>)> = std::mem::take(batch)
+                .into_par_iter()
+                .map(|(mut file, content)| {
+                    file.hash = hash_buffer(&content);
+                    let compressed = (!seen_hashes.contains(&file.hash))
+                        .then(|| compress(&content, compression_level));
+                    (file, compressed)
+                })
+                .collect();
+
+            let mut deduplicated = 0;
+            for (file, compressed) in processed {
+                match compressed {
+                    Some(compressed) if !seen_hashes.contains(&file.hash) => {
+                        insert_blob_stmt.execute(params![
+                            file.hash,
+                            compressed,
+                            file.size,
+                            compressed.len() as u64
+                        ])?;
+                        seen_hashes.insert(file.hash.clone());
+                    }
+                    _ => deduplicated += 1,
+                }
+                add_file(files, file_indices, file);
+            }
+            Ok(deduplicated)
+        };
+
+        for entry in archive.entries()? {
+            let mut entry = entry?;
+            let Some(path) = normalize_path(&entry.path()?) else {
+                continue;
+            };
+            let header = entry.header();
+            let mode = header.mode()? & 0o7777;
+            let mtime = header.mtime()? as i64 * 1000;
+
+            match header.entry_type() {
+                EntryType::Regular | EntryType::Continuous => {
+                    let mut content = Vec::with_capacity(entry.size() as usize);
+                    entry.read_to_end(&mut content)?;
+
+                    if path.rsplit('/').next() == Some("package.json")
+                        || path == "package-lock.json"
+                        || path == "yarn.lock"
+                    {
+                        manifests.insert(path.clone(), String::from_utf8_lossy(&content).into());
+                    }
+
+                    if let Some(progress) = on_progress {
+                        progress(read.get().min(total), total, &path);
+                    }
+
+                    batch_size += content.len();
+                    batch.push((
+                        ArchiveFile {
+                            path,
+                            hash: String::new(),
+                            size: content.len() as u64,
+                            mode: S_IFREG | mode,
+                            mtime,
+                        },
+                        content,
+                    ));
+                    if batch_size >= BATCH_SIZE {
+                        deduplicated += flush(&mut batch, &mut files, &mut file_indices)?;
+                        batch_size = 0;
+                    }
+                }
+                // A hard link repeats an earlier file's content.
+                EntryType::Link => {
+                    let Some(target) = entry.link_name()?.and_then(|t| normalize_path(&t)) else {
+                        continue;
+                    };
+                    deduplicated += flush(&mut batch, &mut files, &mut file_indices)?;
+                    batch_size = 0;
+                    let Some(&index) = file_indices.get(&target) else {
+                        continue;
+                    };
+                    let file = ArchiveFile {
+                        path,
+                        hash: files[index].hash.clone(),
+                        size: files[index].size,
+                        mode: S_IFREG | mode,
+                        mtime,
+                    };
+                    deduplicated += 1;
+                    add_file(&mut files, &mut file_indices, file);
+                }
+                EntryType::Directory => directories.push((path, S_IFDIR | mode)),
+                // Symlinks aren't recorded, as in `pack`.
+                _ => {}
+            }
+        }
+        deduplicated += flush(&mut batch, &mut files, &mut file_indices)?;
+
+        Ok(())
+    })?;
+
+    if let Some(progress) = on_progress {
+        progress(total, total, "Done");
+    }
+
+    Ok(group_packages(files, directories, manifests, deduplicated))
+}
+
+fn add_file(
+    files: &mut Vec<ArchiveFile>,
+    file_indices: &mut HashMap<String, usize>,
+    file: ArchiveFile,
+) {
+    // A path appearing twice is overwritten on extraction; keep the last.
+    match file_indices.get(&file.path) {
+        Some(&index) => files[index] = file,
+        None => {
+            file_indices.insert(file.path.clone(), files.len());
+            files.push(file);
+        }
+    }
+}
+
+/// Sorts archive entries into the packages `scan_node_modules` or
+/// `scan_yarn_pnp` would find in the extracted archive: a Yarn PnP project
+/// when it has `.pnp.cjs` at its root, a project when it has a
+/// node_modules, otherwise node_modules' own contents.
+fn group_packages(
+    files: Vec<ArchiveFile>,
+    directories: Vec<(String, u32)>,
+    manifests: HashMap<String, String>,
+    deduplicated: usize,
+) -> ImportedArchive {
+    let pnp = files.iter().any(|f| f.path == ".pnp.cjs");
+    let prefix = if !pnp
+        && files
+            .iter()
+            .map(|f| &f.path)
+            .chain(directories.iter().map(|(path, _)| path))
+            .any(|p| p.starts_with("node_modules/"))
+    {
+        "node_modules/"
+    } else {
+        ""
+    };
+    let is_project = pnp || !prefix.is_empty();
+
+    let pnpm = !pnp
+        && files
+            .iter()
+            .map(|f| &f.path)
+            .chain(directories.iter().map(|(path, _)| path))
+            .filter_map(|p| relative_components(p, prefix))
+            .any(|c| c[0] == ".pnpm");
+    let package_depth = |components: &[&str]| {
+        if pnp {
+            unplugged_package_depth(components)
+        } else if pnpm {
+            pnpm_package_depth(components)
+        } else {
+            package_depth(components)
+        }
+    };
+
+    let mut hashes = HashMap::new();
+    let mut packages: BTreeMap<String, (Vec<FileEntry>, Vec<DirectoryEntry>)> = BTreeMap::new();
+    let mut root_files = Vec::new();
+    let mut cache_packages = Vec::new();
+
+    for (path, mode) in &directories {
+        let Some(components) = relative_components(path, prefix) else {
+            continue;
+        };
+        let Some(depth) = package_depth(&components) else {
+            continue;
+        };
+        if components.len() < depth {
+            continue;
+        }
+        let package = packages.entry(components[..depth].join("/")).or_default();
+        if components.len() > depth {
+            package.1.push(DirectoryEntry {
+                relative_path: components[depth..].join("/"),
+                mode: *mode,
+            });
+        }
+    }
+
+    for file in files {
+        let Some(components) = relative_components(&file.path, prefix) else {
+            continue;
+        };
+        let entry = |relative_path: String| FileEntry {
+            relative_path,
+            absolute_path: PathBuf::from(&file.path),
+            mode: file.mode,
+            size: file.size,
+            mtime: file.mtime,
+        };
+
+        if pnp {
+            if PNP_ROOT_FILES.contains(&file.path.as_str()) {
+                root_files.push(entry(file.path.clone()));
+            } else if let Some(name) = file
+                .path
+                .strip_prefix(PNP_CACHE_DIR)
+                .and_then(|p| p.strip_prefix('/'))
+                .filter(|name| !name.contains('/'))
+            {
+                cache_packages.extend(pnp_cache_package(entry(name.to_string())));
+            } else if let Some(depth) = package_depth(&components).filter(|d| components.len() > *d)
+            {
+                let package = packages.entry(components[..depth].join("/")).or_default();
+                package.0.push(entry(components[depth..].join("/")));
+            } else {
+                continue;
+            }
+        } else if components.len() == 1 || (components.len() == 2 && components[0] == ".pnpm") {
+            root_files.push(entry(components.join("/")));
+        } else if let Some(depth) = package_depth(&components).filter(|d| components.len() > *d) {
+            let package = packages.entry(components[..depth].join("/")).or_default();
+            package.0.push(entry(components[depth..].join("/")));
+        } else {
+            continue;
+        }
+        hashes.insert(PathBuf::from(&file.path), file.hash);
+    }
+
+    let mut scanned: Vec<ScannedPackage> = cache_packages;
+    scanned.extend(packages.into_iter().map(|(path, (files, directories))| {
+        let package_json = manifests.get(&format!("{}{}/package.json", prefix, path));
+        scanned_package(&path, package_json.map(|s| s.as_str()), files, directories)
+    }));
+    scanned.extend(root_package(root_files));
+
+    let root_dependencies = if is_project {
+        manifests
+            .get("package.json")
+            .map(|content| project_dependencies(content))
+            .unwrap_or_default()
+    } else {
+        Vec::new()
+    };
+    let lockfile = if pnp {
+        "yarn.lock"
+    } else {
+        "package-lock.json"
+    };
+    let lockfile_hash = manifests
+        .get(lockfile)
+        .filter(|_| is_project)
+        .map(|content| hash_string(content));
+
+    let total_files = scanned.iter().map(|p| p.files.len()).sum();
+    let total_size = scanned
+        .iter()
+        .flat_map(|p| p.files.iter())
+        .map(|f| f.size)
+        .sum();
+
+    ImportedArchive {
+        scan_result: ScanResult {
+            layout: if pnp {
+                Layout::YarnPnp
+            } else {
+                Layout::NodeModules
+            },
+            packages: scanned,
+            total_files,
+            total_size,
+        },
+        hashes,
+        root_dependencies,
+        lockfile_hash,
+        deduplicated,
+    }
+}
+
+/// Components of an archive path below `prefix`, if it is below it.
+fn relative_components<'a>(path: &'a str, prefix: &str) -> Option<Vec<&'a str>> {
+    path.strip_prefix(prefix).map(|p| p.split('/').collect())
+}
+
+/// How many leading components of a path below node_modules name its
+/// package directory (`foo`, `@scope/foo`), following `find_package_dirs`.
+fn package_depth(components: &[&str]) -> Option<usize> {
+    match components.first()? {
+        &".bin" | &".cache" | &".pnpm" => None,
+        scope if scope.starts_with('@') => Some(2),
+        _ => Some(1),
+    }
+}
+
+/// Same for a pnpm layout, where packages live at
+/// `.pnpm/<name>/node_modules/<pkg>`, following `find_pnpm_package_dirs`.
+fn pnpm_package_depth(components: &[&str]) -> Option<usize> {
+    match components {
+        [".pnpm", name, "node_modules", pkg, ..]
+            if *name != "node_modules" && !name.starts_with('.') && *pkg != ".bin" =>
+        {
+            Some(if pkg.starts_with('@') { 5 } else { 4 })
+        }
+        _ => None,
+    }
+}
+
+/// Same for the unplugged packages of a Yarn PnP project, at
+/// `.yarn/unplugged/<locator>/node_modules/<pkg>`.
+fn unplugged_package_depth(components: &[&str]) -> Option<usize> {
+    let unplugged: Vec<&str> = PNP_UNPLUGGED_DIR.split('/').collect();
+    let rest = components.strip_prefix(unplugged.as_slice())?;
+    match rest {
+        [_, "node_modules", inner @ ..] => Some(unplugged.len() + 2 + package_depth(inner)?),
+        _ => None,
+    }
+}
+
+/// An entry's path with `./` components removed, or `None` when it would
+/// escape the archive root.
+fn normalize_path(path: &Path) -> Option<String> {
+    let mut components = Vec::new();
+    for component in path.components() {
+        match component {
+            Component::Normal(name) => components.push(name.to_string_lossy()),
+            Component::CurDir | Component::RootDir => {}
+            Component::ParentDir | Component::Prefix(_) => return None,
+        }
+    }
+    (!components.is_empty()).then(|| components.join("/"))
+}
+
+fn open_archive<R: Read + 'static>(reader: R) -> Result<Box<dyn Read>> {
+    let mut reader = BufReader::new(reader);
+    let magic = reader.fill_buf()?;
+
+    if magic.starts_with(&GZIP_MAGIC) {
+        Ok(Box::new(GzDecoder::new(reader)))
+    } else if magic.starts_with(&ZSTD_MAGIC) {
+        Ok(Box::new(zstd::Decoder::with_buffer(reader)?))
+    } else {
+        Ok(Box::new(reader))
+    }
+}
+
+/// Counts the bytes read from the archive file, for progress.
+struct CountingReader<R> {
+    inner: R,
+    read: Rc<Cell<usize>>,
+}
+
+impl<R: Read> Read for CountingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.read.set(self.read.get() + n);
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+    use tar::{Builder, Header};
+
+    fn append_file<W: std::io::Write>(builder: &mut Builder<W>, path: &str, content: &[u8]) {
+        let mut header = Header::new_gnu();
+        header.set_size(content.len() as u64);
+        header.set_mode(0o755);
+        header.set_mtime(1_700_000_000);
+        builder.append_data(&mut header, path, content).unwrap();
+    }
+
+    #[test]
+    fn test_import_archive() {
+        let dir = tempfile::tempdir().unwrap();
+        let archive_path = dir.path().join("node_modules.tar.gz");
+
+        let encoder = GzEncoder::new(File::create(&archive_path).unwrap(), Compression::default());
+        let mut builder = Builder::new(encoder);
+        append_file(
+            &mut builder,
+            "./package.json",
+            br#"{"devDependencies":{"cli":"1"}}"#,
+        );
+        append_file(
+            &mut builder,
+            "./node_modules/cli/package.json",
+            br#"{"name":"cli","version":"1.0.0","bin":"cli.js"}"#,
+        );
+        append_file(&mut builder, "./node_modules/cli/cli.js", b"same");
+        append_file(&mut builder, "./node_modules/@s/lib/index.js", b"same");
+        append_file(&mut builder, "./node_modules/.package-lock.json", b"{}");
+        append_file(&mut builder, "./node_modules/.bin/cli", b"#!/bin/sh");
+        builder.into_inner().unwrap().finish().unwrap();
+
+        let mut store = Store::open(dir.path().join("out.db").to_str().unwrap()).unwrap();
+        let imported = import_archive(&archive_path, &mut store, 6, None).unwrap();
+        let result = &imported.scan_result;
+
+        let mut packages: Vec<(&str, &str, usize)> = result
+            .packages
+            .iter()
+            .map(|p| (p.info.name.as_str(), p.info.path.as_str(), p.files.len()))
+            .collect();
+        packages.sort();
+        assert_eq!(
+            packages,
+            vec![(".root", "", 1), ("@s/lib", "@s/lib", 1), ("cli", "cli", 2)]
+        );
+        assert_eq!(result.layout, Layout::NodeModules);
+        assert_eq!(imported.deduplicated, 1);
+        assert_eq!(imported.root_dependencies[0].name, "cli");
+
+        let cli = result
+            .packages
+            .iter()
+            .find(|p| p.info.name == "cli")
+            .unwrap();
+        assert_eq!(cli.bins[0].path, "cli.js");
+        let file = cli
+            .files
+            .iter()
+            .find(|f| f.relative_path == "cli.js")
+            .unwrap();
+        assert_eq!(file.mode, 0o100755);
+        assert_eq!(file.mtime, 1_700_000_000_000);
+        let hash = &imported.hashes[&file.absolute_path];
+        assert_eq!(store.get_blob_size(hash).unwrap(), Some(4));
+    }
+
+    #[test]
+    fn test_package_depth() {
+        assert_eq!(package_depth(&["lodash", "index.js"]), Some(1));
+        assert_eq!(package_depth(&["@babel", "core", "lib"]), Some(2));
+        assert_eq!(package_depth(&[".bin", "tsc"]), None);
+        assert_eq!(
+            pnpm_package_depth(&[".pnpm", "a@1.0.0", "node_modules", "a", "index.js"]),
+            Some(4)
+        );
+        assert_eq!(
+            pnpm_package_depth(&[".pnpm", "a@1.0.0", "node_modules", ".bin", "a"]),
+            None
+        );
+        assert_eq!(
+            unplugged_package_depth(&[
+                ".yarn",
+                "unplugged",
+                "esbuild-npm-0.20.0",
+                "node_modules",
+                "esbuild",
+                "bin"
+            ]),
+            Some(5)
+        );
+    }
+}
@@ -0,0 +1,119 @@
+use anyhow::Result;
+use std::collections::HashSet;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// Sidecar written into the output directory while an unpack is running.
+pub const JOURNAL_FILE_NAME: &str = ".mohyung-unpack.journal";
+
+const JOURNAL_HEADER_PREFIX: &str = "mohyung-unpack";
+
+/// Append-only log of file ids that have been fully written.
+///
+/// The first line identifies the snapshot being extracted; every following
+/// line is a `files.id`. A journal that is still present means the previous
+/// unpack into that directory did not finish.
+pub struct Journal {
+    path: PathBuf,
+    writer: Mutex<BufWriter<File>>,
+}
+
+impl Journal {
+    pub fn path_for(output_path: &Path) -> PathBuf {
+        output_path.join(JOURNAL_FILE_NAME)
+    }
+
+    /// Returns the completed file ids if `output_path` holds a journal for
+    /// the same snapshot.
+    pub fn load(output_path: &Path, snapshot_id: &str) -> Result<Option<HashSet<i64>>> {
+        let path = Self::path_for(output_path);
+        if !path.exists() {
+            return Ok(None);
+        }
+
+        let mut lines = BufReader::new(File::open(&path)?).lines();
+        let header = lines.next().transpose()?.unwrap_or_default();
+        if header != format_header(snapshot_id) {
+            return Ok(None);
+        }
+
+        let mut completed = HashSet::new();
+        for line in lines {
+            // A torn last line from an interrupted write is simply ignored.
+            if let Ok(id) = line?.trim().parse::<i64>() {
+                completed.insert(id);
+            }
+        }
+        Ok(Some(completed))
+    }
+
+    /// Opens the journal for appending, starting a new one unless `resume`.
+    pub fn open(output_path: &Path, snapshot_id: &str, resume: bool) -> Result<Self> {
+        fs::create_dir_all(output_path)?;
+        let path = Self::path_for(output_path);
+
+        let file = if resume {
+            OpenOptions::new().append(true).open(&path)?
+        } else {
+            let mut file = File::create(&path)?;
+            writeln!(file, "{}", format_header(snapshot_id))?;
+            file
+        };
+
+        Ok(Journal {
+            path,
+            writer: Mutex::new(BufWriter::new(file)),
+        })
+    }
+
+    /// Appends `ids` and flushes them. A failed write stops the unpack
+    /// rather than letting it go on with a journal that no longer records
+    /// progress.
+    pub fn record(&self, ids: &[i64]) -> Result<()> {
+        let mut writer = self.writer.lock().unwrap();
+        for id in ids {
+            writeln!(writer, "{}", id)?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Removes the journal once every file has been written.
+    pub fn finish(self) -> Result<()> {
+        drop(self.writer);
+        fs::remove_file(&self.path)?;
+        Ok(())
+    }
+}
+
+fn format_header(snapshot_id: &str) -> String {
+    format!("{} {}", JOURNAL_HEADER_PREFIX, snapshot_id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_journal_roundtrip() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let journal = Journal::open(dir.path(), "snap-1", false).unwrap();
+        journal.record(&[1, 2]).unwrap();
+        drop(journal);
+
+        let journal = Journal::open(dir.path(), "snap-1", true).unwrap();
+        journal.record(&[5]).unwrap();
+        drop(journal);
+
+        let completed = Journal::load(dir.path(), "snap-1").unwrap().unwrap();
+        assert_eq!(completed, HashSet::from([1, 2, 5]));
+        assert!(Journal::load(dir.path(), "snap-2").unwrap().is_none());
+
+        let journal = Journal::open(dir.path(), "snap-1", true).unwrap();
+        journal.finish().unwrap();
+        assert!(Journal::load(dir.path(), "snap-1").unwrap().is_none());
+    }
+}
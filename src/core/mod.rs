@@ -1,4 +1,15 @@
+pub mod comparer;
+pub mod differ;
+pub mod exclude;
+pub mod exporter;
 pub mod extractor;
+pub mod filter;
+pub mod graph;
 pub mod hasher;
+pub mod importer;
+pub mod journal;
+pub mod npm_cache;
+pub mod production;
 pub mod scanner;
 pub mod store;
+pub mod workspace;
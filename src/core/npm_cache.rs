@@ -0,0 +1,463 @@
+use anyhow::{bail, Context, Result};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use flate2::read::GzDecoder;
+use sha2::{Digest, Sha256, Sha384, Sha512};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::Read;
+use std::path::{Component, Path, PathBuf};
+use tar::{Archive, EntryType};
+
+use crate::core::exclude::IGNORE_FILE_NAME;
+use crate::core::hasher::hash_buffer;
+use crate::types::ProgressFn;
+
+/// A package-lock.json entry that is installed from a registry tarball.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LockedPackage {
+    /// Project-relative install path, e.g. `node_modules/a/node_modules/b`.
+    pub path: String,
+    pub version: Option<String>,
+    pub integrity: Option<String>,
+    pub optional: bool,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct CacheInstall {
+    pub packages: usize,
+    /// Optional packages whose tarball isn't cached, usually other
+    /// platforms' binaries npm never downloaded.
+    pub skipped_optional: Vec<String>,
+    /// Packages whose tarball couldn't be checked against their integrity.
+    pub unverified: Vec<String>,
+}
+
+/// The npm cache's content store: `$npm_config_cache/_cacache`, by default
+/// `~/.npm/_cacache`. `cache_dir` may point at either directory.
+pub fn cacache_dir(cache_dir: Option<&str>) -> PathBuf {
+    let base = match cache_dir {
+        Some(dir) => PathBuf::from(dir),
+        None => match std::env::var_os("npm_config_cache") {
+            Some(dir) => PathBuf::from(dir),
+            None => {
+                let home = std::env::var_os("HOME")
+                    .or_else(|| std::env::var_os("USERPROFILE"))
+                    .unwrap_or_default();
+                PathBuf::from(home).join(".npm")
+            }
+        },
+    };
+
+    if base.join("content-v2").is_dir() {
+        base
+    } else {
+        base.join("_cacache")
+    }
+}
+
+/// Where cacache stores content with the given SRI `integrity`
+/// (`content-v2/<algo>/<hex[0..2]>/<hex[2..4]>/<hex[4..]>`), using the
+/// strongest of its hashes.
+pub fn content_path(cacache: &Path, integrity: &str) -> Option<PathBuf> {
+    let (algorithm, digest) = strongest_hash(integrity)?;
+    let hex: String = digest.iter().map(|b| format!("{:02x}", b)).collect();
+    if hex.len() < 5 {
+        return None;
+    }
+
+    Some(
+        cacache
+            .join("content-v2")
+            .join(algorithm)
+            .join(&hex[..2])
+            .join(&hex[2..4])
+            .join(&hex[4..]),
+    )
+}
+
+fn strongest_hash(integrity: &str) -> Option<(&str, Vec<u8>)> {
+    let hashes: Vec<(&str, &str)> = integrity
+        .split_whitespace()
+        .filter_map(|hash| hash.split_once('-'))
+        .collect();
+
+    ["sha512", "sha384", "sha256", "sha1"]
+        .iter()
+        .find_map(|algorithm| hashes.iter().find(|(a, _)| a == algorithm))
+        .and_then(|(algorithm, digest)| {
+            // Options such as `?foo` may follow the digest.
+            let digest = digest.split('?').next().unwrap_or_default();
+            Some((*algorithm, STANDARD.decode(digest).ok()?))
+        })
+}
+
+/// Whether `content` matches `integrity`, or `None` when its strongest
+/// hash uses an algorithm that can't be checked (sha1).
+fn verify_integrity(content: &[u8], integrity: &str) -> Option<bool> {
+    let (algorithm, digest) = strongest_hash(integrity)?;
+    match algorithm {
+        "sha512" => Some(Sha512::digest(content).as_slice() == digest),
+        "sha384" => Some(Sha384::digest(content).as_slice() == digest),
+        "sha256" => Some(Sha256::digest(content).as_slice() == digest),
+        _ => None,
+    }
+}
+
+/// A tarball read from the npm cache.
+#[derive(Debug, Clone)]
+pub struct CachedTarball {
+    pub content: Vec<u8>,
+    /// False when the integrity only has a hash that can't be checked.
+    pub verified: bool,
+}
+
+/// Reads the cached tarball with the given `integrity`, checking it against
+/// the hash. `None` when it isn't cached.
+pub fn read_cached_tarball(cacache: &Path, integrity: &str) -> Result<Option<CachedTarball>> {
+    let Some(path) = content_path(cacache, integrity).filter(|p| p.is_file()) else {
+        return Ok(None);
+    };
+
+    let content = fs::read(&path)?;
+    let verified = match verify_integrity(&content, integrity) {
+        Some(true) => true,
+        Some(false) => bail!("Integrity mismatch for {}", path.display()),
+        None => false,
+    };
+    Ok(Some(CachedTarball { content, verified }))
+}
+
+/// Registry-installed entries of a v2+ package-lock.json. Links
+/// (workspaces) and bundled dependencies, which arrive inside their
+/// parent's tarball, are left out.
+pub fn read_locked_packages(lockfile_path: &Path) -> Result<Vec<LockedPackage>> {
+    let content = fs::read_to_string(lockfile_path)
+        .with_context(|| format!("Cannot read {}", lockfile_path.display()))?;
+    let parsed: serde_json::Value = serde_json::from_str(&content)
+        .with_context(|| format!("Invalid {}", lockfile_path.display()))?;
+    let Some(entries) = parsed["packages"].as_object() else {
+        bail!(
+            "{} has no \"packages\" map; lockfileVersion 2 or later is required",
+            lockfile_path.display()
+        );
+    };
+
+    let mut packages: Vec<LockedPackage> = entries
+        .iter()
+        .filter(|(key, entry)| {
+            (key.starts_with("node_modules/") || key.contains("/node_modules/"))
+                && !entry["link"].as_bool().unwrap_or(false)
+                && !entry["inBundle"].as_bool().unwrap_or(false)
+        })
+        .map(|(key, entry)| LockedPackage {
+            path: key.clone(),
+            version: entry["version"].as_str().map(|s| s.to_string()),
+            integrity: entry["integrity"].as_str().map(|s| s.to_string()),
+            optional: entry["optional"].as_bool().unwrap_or(false)
+                || entry["devOptional"].as_bool().unwrap_or(false),
+        })
+        .collect();
+    packages.sort_by(|a, b| a.path.cmp(&b.path));
+
+    Ok(packages)
+}
+
+/// Lays out the project's lockfile as an installed tree under `dest`, from
+/// tarballs in the npm cache, without touching the network. The project's
+/// package.json files, lockfile and ignore file are copied alongside so
+/// `pack` sees the same project.
+pub fn install_from_cache(
+    project_path: &Path,
+    cacache: &Path,
+    dest: &Path,
+    on_progress: Option<&ProgressFn<'_>>,
+) -> Result<CacheInstall> {
+    let lockfile_path = project_path.join("package-lock.json");
+    let packages = read_locked_packages(&lockfile_path)?;
+
+    for file in ["package.json", "package-lock.json", IGNORE_FILE_NAME] {
+        if project_path.join(file).is_file() {
+            fs::copy(project_path.join(file), dest.join(file))?;
+        }
+    }
+    copy_workspace_manifests(project_path, &lockfile_path, dest)?;
+
+    let mut install = CacheInstall::default();
+    let mut missing = Vec::new();
+
+    for (index, pkg) in packages.iter().enumerate() {
+        if let Some(progress) = on_progress {
+            progress(index + 1, packages.len(), &pkg.path);
+        }
+        if !is_safe_path(Path::new(&pkg.path)) {
+            bail!("Invalid package path in lockfile: {}", pkg.path);
+        }
+
+        let tarball = match pkg.integrity.as_deref() {
+            Some(integrity) => read_cached_tarball(cacache, integrity)
+                .with_context(|| format!("Cannot read {}", pkg.path))?,
+            None => None,
+        };
+        let Some(tarball) = tarball else {
+            if pkg.optional {
+                install.skipped_optional.push(pkg.path.clone());
+            } else {
+                missing.push(pkg.path.clone());
+            }
+            continue;
+        };
+        if !tarball.verified {
+            install.unverified.push(pkg.path.clone());
+        }
+
+        extract_tarball(&tarball.content, &dest.join(&pkg.path))
+            .with_context(|| format!("Cannot extract {}", pkg.path))?;
+        install.packages += 1;
+    }
+
+    if !missing.is_empty() {
+        let shown: Vec<&str> = missing.iter().take(10).map(|s| s.as_str()).collect();
+        bail!(
+            "{} packages are not in the npm cache ({}): {}{}",
+            missing.len(),
+            cacache.display(),
+            shown.join(", "),
+            if missing.len() > shown.len() {
+                ", ..."
+            } else {
+                ""
+            }
+        );
+    }
+
+    Ok(install)
+}
+
+/// Copies the package.json of every workspace listed in the lockfile (keys
+/// outside node_modules), so workspace snapshots find them.
+fn copy_workspace_manifests(project_path: &Path, lockfile_path: &Path, dest: &Path) -> Result<()> {
+    let content = fs::read_to_string(lockfile_path)?;
+    let parsed: serde_json::Value = serde_json::from_str(&content)?;
+    let Some(entries) = parsed["packages"].as_object() else {
+        return Ok(());
+    };
+
+    for key in entries.keys() {
+        if key.is_empty() || key.contains("node_modules/") || !is_safe_path(Path::new(key)) {
+            continue;
+        }
+        let manifest = project_path.join(key).join("package.json");
+        if manifest.is_file() {
+            fs::create_dir_all(dest.join(key))?;
+            fs::copy(&manifest, dest.join(key).join("package.json"))?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Extracts a registry tarball into `dest`, dropping its top-level
+/// directory (`package/`) and normalizing modes the way npm does: 0755 for
+/// directories and executables, 0644 for other files. Links are skipped.
+fn extract_tarball(tarball: &[u8], dest: &Path) -> Result<()> {
+    fs::create_dir_all(dest)?;
+    let mut archive = Archive::new(GzDecoder::new(tarball));
+
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let Some(relative) = package_relative_path(&entry.path()?) else {
+            continue;
+        };
+        let target = dest.join(&relative);
+
+        match entry.header().entry_type() {
+            EntryType::Directory => {
+                fs::create_dir_all(&target)?;
+            }
+            EntryType::Regular | EntryType::Continuous => {
+                if let Some(parent) = target.parent() {
+                    fs::create_dir_all(parent)?;
+                }
+                let mode = entry.header().mode()?;
+                let mtime = entry.header().mtime()?;
+
+                let mut content = Vec::new();
+                entry.read_to_end(&mut content)?;
+                fs::write(&target, &content)?;
+
+                #[cfg(unix)]
+                {
+                    use std::os::unix::fs::PermissionsExt;
+                    let mode = if mode & 0o111 != 0 { 0o755 } else { 0o644 };
+                    fs::set_permissions(&target, fs::Permissions::from_mode(mode))?;
+                }
+                filetime::set_file_mtime(
+                    &target,
+                    filetime::FileTime::from_unix_time(mtime as i64, 0),
+                )?;
+            }
+            _ => {}
+        }
+    }
+
+    Ok(())
+}
+
+/// `relative_path -> SHA-256` of the regular files in a registry tarball,
+/// the same hashes the snapshot stores for its blobs.
+pub fn tarball_file_hashes(tarball: &[u8]) -> Result<BTreeMap<String, String>> {
+    let mut archive = Archive::new(GzDecoder::new(tarball));
+    let mut hashes = BTreeMap::new();
+
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        if !matches!(
+            entry.header().entry_type(),
+            EntryType::Regular | EntryType::Continuous
+        ) {
+            continue;
+        }
+        let Some(relative) = package_relative_path(&entry.path()?) else {
+            continue;
+        };
+
+        let mut content = Vec::new();
+        entry.read_to_end(&mut content)?;
+        hashes.insert(
+            relative.to_string_lossy().replace('\\', "/"),
+            hash_buffer(&content),
+        );
+    }
+
+    Ok(hashes)
+}
+
+/// A tarball entry's path without its top-level directory, or `None` for
+/// the directory itself and paths escaping the package.
+fn package_relative_path(path: &Path) -> Option<PathBuf> {
+    let relative: PathBuf = path.components().skip(1).collect();
+    if relative.as_os_str().is_empty() || !is_safe_path(&relative) {
+        None
+    } else {
+        Some(relative)
+    }
+}
+
+fn is_safe_path(path: &Path) -> bool {
+    path.components().all(|c| matches!(c, Component::Normal(_)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+    use tar::{Builder, Header};
+
+    fn tarball(files: &[(&str, &[u8], u32)]) -> Vec<u8> {
+        let mut builder = Builder::new(GzEncoder::new(Vec::new(), Compression::default()));
+        for (path, content, mode) in files {
+            let mut header = Header::new_gnu();
+            header.set_size(content.len() as u64);
+            header.set_mode(*mode);
+            header.set_mtime(499_162_500);
+            builder.append_data(&mut header, path, *content).unwrap();
+        }
+        builder.into_inner().unwrap().finish().unwrap()
+    }
+
+    fn cache(cacache: &Path, content: &[u8]) -> String {
+        let integrity = format!("sha512-{}", STANDARD.encode(Sha512::digest(content)));
+        let path = content_path(cacache, &integrity).unwrap();
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, content).unwrap();
+        integrity
+    }
+
+    #[test]
+    fn test_content_path() {
+        let path = content_path(Path::new("/c"), "sha1-AAEC sha512-AAECAwQ=").unwrap();
+        assert_eq!(path, Path::new("/c/content-v2/sha512/00/01/020304"));
+        assert!(content_path(Path::new("/c"), "md5-???").is_none());
+    }
+
+    #[test]
+    fn test_verify_integrity() {
+        let content = b"tarball";
+        let sha384 = format!("sha384-{}", STANDARD.encode(Sha384::digest(content)));
+        assert_eq!(verify_integrity(content, &sha384), Some(true));
+        assert_eq!(verify_integrity(b"tampered", &sha384), Some(false));
+        assert_eq!(verify_integrity(content, "sha1-AAEC"), None);
+    }
+
+    #[test]
+    fn test_tarball_file_hashes() {
+        let content = tarball(&[
+            ("package/package.json", b"{}", 0o644),
+            ("package/lib/index.js", b"x", 0o644),
+        ]);
+        let hashes = tarball_file_hashes(&content).unwrap();
+        assert_eq!(
+            hashes.keys().collect::<Vec<_>>(),
+            vec!["lib/index.js", "package.json"]
+        );
+        assert_eq!(hashes["lib/index.js"], hash_buffer(b"x"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_install_from_cache() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let project = dir.path().join("project");
+        let cacache = dir.path().join("_cacache");
+        let dest = dir.path().join("staging");
+        fs::create_dir_all(&project).unwrap();
+        fs::create_dir_all(&dest).unwrap();
+
+        let cli = tarball(&[
+            (
+                "package/package.json",
+                br#"{"name":"cli","version":"1.0.0"}"#,
+                0o664,
+            ),
+            ("package/bin/cli.js", b"#!/usr/bin/env node\n", 0o775),
+        ]);
+        let cli_integrity = cache(&cacache, &cli);
+        fs::write(
+            project.join("package-lock.json"),
+            serde_json::json!({
+                "lockfileVersion": 3,
+                "packages": {
+                    "": { "dependencies": { "cli": "^1.0.0" } },
+                    "node_modules/cli": { "version": "1.0.0", "integrity": cli_integrity },
+                    "node_modules/fsevents": {
+                        "version": "2.3.3",
+                        "integrity": "sha512-AAECAwQ=",
+                        "optional": true
+                    }
+                }
+            })
+            .to_string(),
+        )
+        .unwrap();
+
+        let install = install_from_cache(&project, &cacache, &dest, None).unwrap();
+        assert_eq!(install.packages, 1);
+        assert_eq!(install.skipped_optional, vec!["node_modules/fsevents"]);
+
+        let bin = dest.join("node_modules/cli/bin/cli.js");
+        assert_eq!(
+            fs::metadata(&bin).unwrap().permissions().mode() & 0o777,
+            0o755
+        );
+        let manifest = dest.join("node_modules/cli/package.json");
+        assert_eq!(
+            fs::metadata(&manifest).unwrap().permissions().mode() & 0o777,
+            0o644
+        );
+        assert!(dest.join("package-lock.json").exists());
+    }
+}
@@ -0,0 +1,283 @@
+use anyhow::{bail, Context, Result};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::core::scanner::{ScannedPackage, ROOT_PACKAGE_NAME};
+use crate::core::workspace::{find_workspace_dirs, read_workspace_patterns};
+use crate::types::Layout;
+
+/// Dependency fields followed when computing the production closure.
+const RUNTIME_DEPENDENCY_FIELDS: [&str; 3] =
+    ["dependencies", "optionalDependencies", "peerDependencies"];
+
+/// Indices of `packages` needed at runtime, i.e. everything except
+/// devDependencies and what only they pull in.
+///
+/// An npm lockfile (v2+) decides directly through its `dev` flags. Otherwise
+/// (pnpm, yarn classic, no lockfile) dependencies are resolved on disk the
+/// way Node does, starting from the root `package.json` and, for workspace
+/// snapshots, each workspace's. Package paths are relative to `base_path`.
+/// The root pseudo-package is always kept.
+pub fn production_packages(
+    project_path: &Path,
+    base_path: &Path,
+    layout: Layout,
+    packages: &[ScannedPackage],
+) -> Result<HashSet<usize>> {
+    if layout == Layout::YarnPnp {
+        bail!("--production is not supported for Yarn PnP projects");
+    }
+
+    let mut keep = match read_npm_lockfile(&project_path.join("package-lock.json"))? {
+        Some(lock_paths) => {
+            // Lockfile keys are project-relative (`node_modules/a`,
+            // `packages/app/node_modules/b`).
+            let prefix = if layout.is_project_rooted() {
+                ""
+            } else {
+                "node_modules/"
+            };
+            packages
+                .iter()
+                .enumerate()
+                .filter(|(_, pkg)| lock_paths.contains(&format!("{}{}", prefix, pkg.info.path)))
+                .map(|(index, _)| index)
+                .collect()
+        }
+        None => resolve_on_disk(project_path, base_path, layout, packages)?,
+    };
+
+    keep.extend(
+        packages
+            .iter()
+            .enumerate()
+            .filter(|(_, pkg)| pkg.info.name == ROOT_PACKAGE_NAME)
+            .map(|(index, _)| index),
+    );
+
+    Ok(keep)
+}
+
+/// Production entries of a package-lock.json `packages` map, or `None` for
+/// a missing or pre-v2 lockfile.
+fn read_npm_lockfile(path: &Path) -> Result<Option<HashSet<String>>> {
+    if !path.exists() {
+        return Ok(None);
+    }
+
+    let content = fs::read_to_string(path)?;
+    let parsed: serde_json::Value =
+        serde_json::from_str(&content).with_context(|| format!("Invalid {}", path.display()))?;
+    let Some(entries) = parsed["packages"].as_object() else {
+        return Ok(None);
+    };
+
+    Ok(Some(
+        entries
+            .iter()
+            // `devOptional` entries are also optional dependencies of
+            // production packages, so `npm install --omit=dev` keeps them.
+            .filter(|(key, entry)| !key.is_empty() && !entry["dev"].as_bool().unwrap_or(false))
+            .map(|(key, _)| key.clone())
+            .collect(),
+    ))
+}
+
+fn resolve_on_disk(
+    project_path: &Path,
+    base_path: &Path,
+    layout: Layout,
+    packages: &[ScannedPackage],
+) -> Result<HashSet<usize>> {
+    let package_dirs: HashMap<PathBuf, usize> = packages
+        .iter()
+        .enumerate()
+        .filter(|(_, pkg)| pkg.info.name != ROOT_PACKAGE_NAME)
+        .filter_map(|(index, pkg)| {
+            let dir = base_path.join(&pkg.info.path).canonicalize().ok()?;
+            Some((dir, index))
+        })
+        .collect();
+
+    let mut roots = vec![project_path.to_path_buf()];
+    if layout == Layout::Workspace {
+        let patterns = read_workspace_patterns(project_path)?;
+        for dir in find_workspace_dirs(project_path, &patterns)? {
+            roots.push(project_path.join(dir));
+        }
+    }
+
+    let mut queue: Vec<PathBuf> = Vec::new();
+    for root in roots {
+        let root = root.canonicalize()?;
+        for name in dependency_names(
+            &root.join("package.json"),
+            &["dependencies", "optionalDependencies"],
+        ) {
+            queue.extend(resolve_package(&root, &name));
+        }
+    }
+
+    let mut visited = HashSet::new();
+    let mut keep = HashSet::new();
+
+    while let Some(dir) = queue.pop() {
+        if !visited.insert(dir.clone()) {
+            continue;
+        }
+
+        // A nested node_modules belongs to the package that contains it.
+        if let Some(index) = dir.ancestors().find_map(|a| package_dirs.get(a)) {
+            keep.insert(*index);
+        }
+
+        for name in dependency_names(&dir.join("package.json"), &RUNTIME_DEPENDENCY_FIELDS) {
+            queue.extend(resolve_package(&dir, &name));
+        }
+    }
+
+    Ok(keep)
+}
+
+fn dependency_names(pkg_json_path: &Path, fields: &[&str]) -> Vec<String> {
+    let Ok(content) = fs::read_to_string(pkg_json_path) else {
+        return Vec::new();
+    };
+    let Ok(parsed) = serde_json::from_str::<serde_json::Value>(&content) else {
+        return Vec::new();
+    };
+
+    fields
+        .iter()
+        .filter_map(|field| parsed[*field].as_object())
+        .flat_map(|deps| deps.keys().cloned())
+        .collect()
+}
+
+/// Node's lookup: `<dir>/node_modules/<name>` for `from` and each ancestor,
+/// following symlinks (pnpm links `.pnpm/<id>/node_modules/<dep>` to the
+/// dependency's own `.pnpm` entry).
+fn resolve_package(from: &Path, name: &str) -> Option<PathBuf> {
+    from.ancestors()
+        .filter(|dir| dir.file_name().is_none_or(|n| n != "node_modules"))
+        .map(|dir| dir.join("node_modules").join(name))
+        .find(|candidate| candidate.is_dir())
+        .and_then(|candidate| candidate.canonicalize().ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::scanner::scan_node_modules;
+
+    fn write_pkg(dir: &Path, json: &str) {
+        fs::create_dir_all(dir).unwrap();
+        fs::write(dir.join("package.json"), json).unwrap();
+    }
+
+    fn kept_paths(packages: &[ScannedPackage], keep: &HashSet<usize>) -> Vec<String> {
+        let mut paths: Vec<String> = keep
+            .iter()
+            .map(|i| packages[*i].info.path.clone())
+            .collect();
+        paths.sort();
+        paths
+    }
+
+    #[test]
+    fn test_production_from_npm_lockfile() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        let nm = root.join("node_modules");
+        write_pkg(
+            &nm.join("express"),
+            r#"{"name":"express","version":"4.0.0"}"#,
+        );
+        write_pkg(&nm.join("jest"), r#"{"name":"jest","version":"29.0.0"}"#);
+        write_pkg(
+            &nm.join("fsevents"),
+            r#"{"name":"fsevents","version":"2.0.0"}"#,
+        );
+        fs::write(
+            root.join("package-lock.json"),
+            r#"{"lockfileVersion":3,"packages":{
+                "":{"name":"app"},
+                "node_modules/express":{"version":"4.0.0"},
+                "node_modules/jest":{"version":"29.0.0","dev":true},
+                "node_modules/fsevents":{"version":"2.0.0","devOptional":true}
+            }}"#,
+        )
+        .unwrap();
+
+        let scanned = scan_node_modules(&nm, None).unwrap();
+        let keep = production_packages(root, &nm, Layout::NodeModules, &scanned.packages).unwrap();
+
+        assert_eq!(
+            kept_paths(&scanned.packages, &keep),
+            vec!["express", "fsevents"]
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_production_resolves_pnpm_links() {
+        use std::os::unix::fs::symlink;
+
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        let nm = root.join("node_modules");
+        fs::write(
+            root.join("package.json"),
+            r#"{"dependencies":{"a":"1"},"devDependencies":{"jest":"29"}}"#,
+        )
+        .unwrap();
+
+        let a = nm.join(".pnpm/a@1.0.0/node_modules/a");
+        let b = nm.join(".pnpm/b@1.0.0/node_modules/b");
+        let jest = nm.join(".pnpm/jest@29.0.0/node_modules/jest");
+        write_pkg(
+            &a,
+            r#"{"name":"a","version":"1.0.0","dependencies":{"b":"1"}}"#,
+        );
+        write_pkg(&b, r#"{"name":"b","version":"1.0.0"}"#);
+        write_pkg(&jest, r#"{"name":"jest","version":"29.0.0"}"#);
+        symlink(&b, nm.join(".pnpm/a@1.0.0/node_modules/b")).unwrap();
+        symlink(&a, nm.join("a")).unwrap();
+        symlink(&jest, nm.join("jest")).unwrap();
+
+        let scanned = scan_node_modules(&nm, None).unwrap();
+        let keep = production_packages(root, &nm, Layout::NodeModules, &scanned.packages).unwrap();
+
+        assert_eq!(
+            kept_paths(&scanned.packages, &keep),
+            vec![
+                ".pnpm/a@1.0.0/node_modules/a",
+                ".pnpm/b@1.0.0/node_modules/b"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_production_includes_nested_node_modules() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        let nm = root.join("node_modules");
+        fs::write(root.join("package.json"), r#"{"dependencies":{"a":"1"}}"#).unwrap();
+        write_pkg(
+            &nm.join("a"),
+            r#"{"name":"a","version":"1.0.0","dependencies":{"b":"2","c":"1"}}"#,
+        );
+        write_pkg(
+            &nm.join("a/node_modules/b"),
+            r#"{"name":"b","version":"2.0.0","dependencies":{"c":"1"}}"#,
+        );
+        write_pkg(&nm.join("b"), r#"{"name":"b","version":"1.0.0"}"#);
+        write_pkg(&nm.join("c"), r#"{"name":"c","version":"1.0.0"}"#);
+
+        let scanned = scan_node_modules(&nm, None).unwrap();
+        let keep = production_packages(root, &nm, Layout::NodeModules, &scanned.packages).unwrap();
+
+        assert_eq!(kept_paths(&scanned.packages, &keep), vec!["a", "c"]);
+    }
+}
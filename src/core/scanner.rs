@@ -4,21 +4,51 @@ use std::fs;
 use std::path::{Path, PathBuf};
 use walkdir::WalkDir;
 
-use crate::types::{FileEntry, PackageInfo};
+use crate::types::{
+    BinEntry, Dependency, DependencyKind, DirectoryEntry, FileEntry, Layout, PackageInfo,
+    ProgressFn, SkippedDir,
+};
 
 #[derive(Debug, Clone)]
 pub struct ScanResult {
+    pub layout: Layout,
     pub packages: Vec<ScannedPackage>,
     pub total_files: usize,
     pub total_size: u64,
 }
 
+impl ScanResult {
+    /// Directories that were stored as anonymous packages, with the reason.
+    pub fn anonymous_packages(&self) -> Vec<SkippedDir> {
+        self.packages
+            .iter()
+            .filter_map(|p| {
+                p.anonymous_reason.as_ref().map(|reason| SkippedDir {
+                    path: p.info.path.clone(),
+                    reason: reason.clone(),
+                })
+            })
+            .collect()
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct ScannedPackage {
     pub info: PackageInfo,
     pub files: Vec<FileEntry>,
+    pub directories: Vec<DirectoryEntry>,
+    pub dependencies: Vec<Dependency>,
+    pub bins: Vec<BinEntry>,
+    /// Set when the directory has no usable package.json.
+    pub anonymous_reason: Option<String>,
 }
 
+/// Name of the pseudo-package holding files that live directly in
+/// node_modules rather than in a package (`.package-lock.json`,
+/// `.modules.yaml`, `.yarn-state.yml`, `.yarn-integrity`, `.pnpm/lock.yaml`).
+/// Not a valid npm name, so it can't collide with a real package.
+pub const ROOT_PACKAGE_NAME: &str = ".root";
+
 #[derive(Debug)]
 struct PackageDir {
     path: PathBuf,
@@ -29,9 +59,33 @@ fn is_pnpm_structure(node_modules_path: &Path) -> bool {
     node_modules_path.join(".pnpm").exists()
 }
 
-fn parse_package_json(pkg_json_path: &Path) -> Option<(String, String)> {
-    let content = fs::read_to_string(pkg_json_path).ok()?;
-    let parsed: serde_json::Value = serde_json::from_str(&content).ok()?;
+/// Yarn Plug'n'Play installs have no node_modules; `.pnp.cjs` marks the
+/// project root and dependencies live in `.yarn/cache` zips.
+pub fn is_yarn_pnp_structure(project_path: &Path) -> bool {
+    project_path.join(".pnp.cjs").is_file()
+}
+
+/// The parts of a package.json the snapshot records.
+#[derive(Debug, Default)]
+struct Manifest {
+    name: String,
+    version: String,
+    dependencies: Vec<Dependency>,
+    bins: Vec<BinEntry>,
+}
+
+fn parse_package_json(pkg_json_path: &Path) -> std::result::Result<Manifest, String> {
+    if !pkg_json_path.exists() {
+        return Err("no package.json".to_string());
+    }
+    let content =
+        fs::read_to_string(pkg_json_path).map_err(|e| format!("unreadable package.json: {}", e))?;
+    parse_manifest(&content)
+}
+
+fn parse_manifest(content: &str) -> std::result::Result<Manifest, String> {
+    let parsed: serde_json::Value =
+        serde_json::from_str(content).map_err(|e| format!("invalid package.json: {}", e))?;
 
     let name = parsed["name"]
         .as_str()
@@ -42,7 +96,83 @@ fn parse_package_json(pkg_json_path: &Path) -> Option<(String, String)> {
         .unwrap_or("0.0.0")
         .to_string();
 
-    Some((name, version))
+    let dependencies = parse_dependencies(&parsed, &DependencyKind::INSTALLED);
+
+    // `"bin": "cli.js"` is shorthand for a command named after the package.
+    let bins = match &parsed["bin"] {
+        serde_json::Value::String(path) => vec![BinEntry {
+            name: name.rsplit('/').next().unwrap_or(&name).to_string(),
+            path: path.trim_start_matches("./").to_string(),
+        }],
+        serde_json::Value::Object(entries) => entries
+            .iter()
+            .filter_map(|(bin_name, path)| {
+                Some(BinEntry {
+                    name: bin_name.clone(),
+                    path: path.as_str()?.trim_start_matches("./").to_string(),
+                })
+            })
+            .collect(),
+        _ => Vec::new(),
+    };
+
+    Ok(Manifest {
+        name,
+        version,
+        dependencies,
+        bins,
+    })
+}
+
+fn parse_dependencies(parsed: &serde_json::Value, kinds: &[DependencyKind]) -> Vec<Dependency> {
+    let mut dependencies = Vec::new();
+    for kind in kinds {
+        let Some(deps) = parsed[kind.as_str()].as_object() else {
+            continue;
+        };
+        for (name, spec) in deps {
+            dependencies.push(Dependency {
+                name: name.clone(),
+                spec: spec.as_str().unwrap_or_default().to_string(),
+                kind: *kind,
+            });
+        }
+    }
+    dependencies
+}
+
+/// Name and version from a package.json, as `pack` would record them.
+pub fn read_package_identity(pkg_json_path: &Path) -> Option<(String, String)> {
+    parse_package_json(pkg_json_path)
+        .ok()
+        .map(|manifest| (manifest.name, manifest.version))
+}
+
+/// Every dependency a project's own package.json declares, including
+/// devDependencies. Empty when there is no readable package.json.
+pub fn read_project_dependencies(pkg_json_path: &Path) -> Vec<Dependency> {
+    let Ok(content) = fs::read_to_string(pkg_json_path) else {
+        return Vec::new();
+    };
+    project_dependencies(&content)
+}
+
+/// Same as `read_project_dependencies`, from the package.json content.
+pub fn project_dependencies(content: &str) -> Vec<Dependency> {
+    let Ok(parsed) = serde_json::from_str::<serde_json::Value>(content) else {
+        return Vec::new();
+    };
+    parse_dependencies(&parsed, &DependencyKind::ALL)
+}
+
+/// Name for a directory without a usable package.json: its path below the
+/// innermost node_modules (`foo`, `@scope/foo`).
+fn anonymous_name(relative_path: &str) -> String {
+    relative_path
+        .rsplit_once("node_modules/")
+        .map(|(_, name)| name)
+        .unwrap_or(relative_path)
+        .to_string()
 }
 
 fn find_package_dirs(node_modules_path: &Path) -> Result<Vec<PackageDir>> {
@@ -151,20 +281,22 @@ fn find_pnpm_package_dirs(node_modules_path: &Path) -> Result<Vec<PackageDir>> {
 }
 
 fn scan_package_files(pkg_dir: &PackageDir) -> Option<ScannedPackage> {
-    let pkg_json_path = pkg_dir.path.join("package.json");
-    let (name, version) = parse_package_json(&pkg_json_path)?;
-
     let mut files = Vec::new();
+    let mut directories = Vec::new();
 
     for entry in WalkDir::new(&pkg_dir.path)
+        .min_depth(1)
         .into_iter()
         .filter_map(|e| e.ok())
     {
-        if !entry.file_type().is_file() {
+        let is_dir = entry.file_type().is_dir();
+        if !entry.file_type().is_file() && !is_dir {
             continue;
         }
 
-        let metadata = entry.metadata().ok()?;
+        let Ok(metadata) = entry.metadata() else {
+            continue;
+        };
         let absolute_path = entry.path().to_path_buf();
         let relative_path = absolute_path
             .strip_prefix(&pkg_dir.path)
@@ -172,44 +304,176 @@ fn scan_package_files(pkg_dir: &PackageDir) -> Option<ScannedPackage> {
             .to_string_lossy()
             .to_string();
 
-        #[cfg(unix)]
-        let mode = {
-            use std::os::unix::fs::PermissionsExt;
-            metadata.permissions().mode()
-        };
-        #[cfg(not(unix))]
-        let mode = 0o644u32;
-
-        let mtime = metadata
-            .modified()
-            .ok()
-            .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
-            .map(|d| d.as_millis() as i64)
-            .unwrap_or(0);
-
-        files.push(FileEntry {
-            relative_path,
-            absolute_path,
-            mode,
-            size: metadata.len(),
-            mtime,
-        });
+        if is_dir {
+            #[cfg(unix)]
+            let mode = {
+                use std::os::unix::fs::PermissionsExt;
+                metadata.permissions().mode()
+            };
+            #[cfg(not(unix))]
+            let mode = 0o755u32;
+
+            directories.push(DirectoryEntry {
+                relative_path,
+                mode,
+            });
+            continue;
+        }
+
+        files.push(file_entry(absolute_path, relative_path, &metadata));
+    }
+
+    let manifest = parse_package_json(&pkg_dir.path.join("package.json"));
+    Some(package_from_manifest(
+        &pkg_dir.relative_path,
+        manifest,
+        files,
+        directories,
+    ))
+}
+
+/// The package recorded for a directory at `relative_path`, named after
+/// its package.json content when there is a usable one.
+pub fn scanned_package(
+    relative_path: &str,
+    package_json: Option<&str>,
+    files: Vec<FileEntry>,
+    directories: Vec<DirectoryEntry>,
+) -> ScannedPackage {
+    let manifest = package_json
+        .ok_or_else(|| "no package.json".to_string())
+        .and_then(parse_manifest);
+    package_from_manifest(relative_path, manifest, files, directories)
+}
+
+fn package_from_manifest(
+    relative_path: &str,
+    manifest: std::result::Result<Manifest, String>,
+    files: Vec<FileEntry>,
+    directories: Vec<DirectoryEntry>,
+) -> ScannedPackage {
+    let (manifest, anonymous_reason) = match manifest {
+        Ok(manifest) => (manifest, None),
+        Err(reason) => {
+            let manifest = Manifest {
+                name: anonymous_name(relative_path),
+                version: "0.0.0".to_string(),
+                ..Manifest::default()
+            };
+            (manifest, Some(reason))
+        }
+    };
+
+    ScannedPackage {
+        info: PackageInfo {
+            id: None,
+            name: manifest.name,
+            version: manifest.version,
+            path: relative_path.to_string(),
+        },
+        files,
+        directories,
+        dependencies: manifest.dependencies,
+        bins: manifest.bins,
+        anonymous_reason,
+    }
+}
+
+fn file_entry(absolute_path: PathBuf, relative_path: String, metadata: &fs::Metadata) -> FileEntry {
+    #[cfg(unix)]
+    let mode = {
+        use std::os::unix::fs::PermissionsExt;
+        metadata.permissions().mode()
+    };
+    #[cfg(not(unix))]
+    let mode = 0o644u32;
+
+    let mtime = metadata
+        .modified()
+        .ok()
+        .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+        .map(|d| d.as_millis() as i64)
+        .unwrap_or(0);
+
+    FileEntry {
+        relative_path,
+        absolute_path,
+        mode,
+        size: metadata.len(),
+        mtime,
+    }
+}
+
+/// Regular files directly inside `dir`, with paths relative to `root`.
+fn read_dir_files(root: &Path, dir: &Path) -> Result<Vec<FileEntry>> {
+    let mut files = Vec::new();
+    if !dir.is_dir() {
+        return Ok(files);
+    }
+
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        if !entry.file_type()?.is_file() {
+            continue;
+        }
+
+        let metadata = entry.metadata()?;
+        let absolute_path = entry.path();
+        let relative_path = absolute_path
+            .strip_prefix(root)?
+            .to_string_lossy()
+            .replace('\\', "/");
+        files.push(file_entry(absolute_path, relative_path, &metadata));
+    }
+
+    Ok(files)
+}
+
+/// The pseudo-package holding `files`, if there are any.
+pub fn root_package(files: Vec<FileEntry>) -> Option<ScannedPackage> {
+    if files.is_empty() {
+        return None;
     }
 
     Some(ScannedPackage {
         info: PackageInfo {
             id: None,
-            name,
-            version,
-            path: pkg_dir.relative_path.clone(),
+            name: ROOT_PACKAGE_NAME.to_string(),
+            version: "0.0.0".to_string(),
+            path: String::new(),
         },
         files,
+        directories: Vec::new(),
+        dependencies: Vec::new(),
+        bins: Vec::new(),
+        anonymous_reason: None,
     })
 }
 
+fn scan_root_files(node_modules_path: &Path) -> Result<Option<ScannedPackage>> {
+    let mut files = read_dir_files(node_modules_path, node_modules_path)?;
+    files.extend(read_dir_files(
+        node_modules_path,
+        &node_modules_path.join(".pnpm"),
+    )?);
+
+    Ok(root_package(files))
+}
+
+/// Package directories `scan_node_modules` would record, relative to
+/// `node_modules_path`.
+pub fn list_package_dirs(node_modules_path: &Path) -> Result<Vec<String>> {
+    let package_dirs = if is_pnpm_structure(node_modules_path) {
+        find_pnpm_package_dirs(node_modules_path)?
+    } else {
+        find_package_dirs(node_modules_path)?
+    };
+    Ok(package_dirs.into_iter().map(|d| d.relative_path).collect())
+}
+
 pub fn scan_node_modules(
     node_modules_path: &Path,
-    on_progress: Option<&dyn Fn(usize, usize, &str)>,
+    on_progress: Option<&ProgressFn<'_>>,
 ) -> Result<ScanResult> {
     let use_pnpm = is_pnpm_structure(node_modules_path);
 
@@ -223,11 +487,15 @@ pub fn scan_node_modules(
         progress(0, package_dirs.len(), "Collecting packages...");
     }
 
-    let packages: Vec<ScannedPackage> = package_dirs
+    let mut packages: Vec<ScannedPackage> = package_dirs
         .par_iter()
-        .filter_map(|pkg_dir| scan_package_files(pkg_dir))
+        .filter_map(scan_package_files)
         .collect();
 
+    if let Some(root) = scan_root_files(node_modules_path)? {
+        packages.push(root);
+    }
+
     let total_files: usize = packages.iter().map(|p| p.files.len()).sum();
     let total_size: u64 = packages
         .iter()
@@ -240,6 +508,176 @@ pub fn scan_node_modules(
     }
 
     Ok(ScanResult {
+        layout: Layout::NodeModules,
+        packages,
+        total_files,
+        total_size,
+    })
+}
+
+/// Install artifacts of a Yarn PnP project, relative to the project root.
+pub const PNP_ROOT_FILES: [&str; 4] = [
+    ".pnp.cjs",
+    ".pnp.loader.mjs",
+    ".pnp.data.json",
+    ".yarn/install-state.gz",
+];
+
+/// Where Yarn PnP keeps package archives. Every archive is recorded as a
+/// package with this path, holding just the zip.
+pub const PNP_CACHE_DIR: &str = ".yarn/cache";
+pub const PNP_UNPLUGGED_DIR: &str = ".yarn/unplugged";
+
+/// Extracts name and version from a Yarn cache archive name such as
+/// `lodash-npm-4.17.21-6382451519-c2dc4ec1e5.zip` or
+/// `@babel-core-npm-7.24.0-0a1b2c3d4e-e5f6a7b8c9.zip`.
+fn parse_cache_zip_name(file_name: &str) -> (String, String) {
+    let stem = file_name.trim_end_matches(".zip");
+
+    let Some((slug, rest)) = stem.split_once("-npm-") else {
+        return (stem.to_string(), "0.0.0".to_string());
+    };
+
+    let name = match slug.strip_prefix('@') {
+        Some(scoped) => match scoped.split_once('-') {
+            Some((scope, name)) => format!("@{}/{}", scope, name),
+            None => slug.to_string(),
+        },
+        None => slug.to_string(),
+    };
+
+    let mut parts = rest.rsplitn(3, '-');
+    let _checksum = parts.next();
+    let _hash = parts.next();
+    let version = parts.next().unwrap_or(rest).to_string();
+
+    (name, version)
+}
+
+fn scan_pnp_cache(project_path: &Path) -> Result<Vec<ScannedPackage>> {
+    let cache_path = project_path.join(PNP_CACHE_DIR);
+    let mut packages = Vec::new();
+
+    for file in read_dir_files(project_path, &cache_path)? {
+        let file_name = file
+            .absolute_path
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .to_string();
+        packages.extend(pnp_cache_package(FileEntry {
+            relative_path: file_name,
+            ..file
+        }));
+    }
+
+    Ok(packages)
+}
+
+/// The package recorded for a Yarn cache archive, whose `relative_path`
+/// is its file name. `None` for anything but a zip.
+pub fn pnp_cache_package(file: FileEntry) -> Option<ScannedPackage> {
+    if !file.relative_path.ends_with(".zip") {
+        return None;
+    }
+
+    let (name, version) = parse_cache_zip_name(&file.relative_path);
+    Some(ScannedPackage {
+        info: PackageInfo {
+            id: None,
+            name,
+            version,
+            path: PNP_CACHE_DIR.to_string(),
+        },
+        files: vec![file],
+        directories: Vec::new(),
+        dependencies: Vec::new(),
+        bins: Vec::new(),
+        anonymous_reason: None,
+    })
+}
+
+/// Packages Yarn had to extract to disk, at
+/// `.yarn/unplugged/<locator>/node_modules/<name>`.
+fn find_unplugged_package_dirs(project_path: &Path) -> Result<Vec<PackageDir>> {
+    let unplugged_path = project_path.join(PNP_UNPLUGGED_DIR);
+    let mut dirs = Vec::new();
+    if !unplugged_path.is_dir() {
+        return Ok(dirs);
+    }
+
+    for entry in fs::read_dir(&unplugged_path)? {
+        let entry = entry?;
+        if !entry.file_type()?.is_dir() {
+            continue;
+        }
+
+        let inner_node_modules = entry.path().join("node_modules");
+        if !inner_node_modules.is_dir() {
+            continue;
+        }
+
+        let locator = entry.file_name().to_string_lossy().to_string();
+        for pkg_dir in find_package_dirs(&inner_node_modules)? {
+            dirs.push(PackageDir {
+                path: pkg_dir.path,
+                relative_path: format!(
+                    "{}/{}/node_modules/{}",
+                    PNP_UNPLUGGED_DIR, locator, pkg_dir.relative_path
+                ),
+            });
+        }
+    }
+
+    Ok(dirs)
+}
+
+pub fn scan_yarn_pnp(
+    project_path: &Path,
+    on_progress: Option<&ProgressFn<'_>>,
+) -> Result<ScanResult> {
+    let unplugged_dirs = find_unplugged_package_dirs(project_path)?;
+
+    if let Some(progress) = on_progress {
+        progress(0, unplugged_dirs.len(), "Collecting packages...");
+    }
+
+    let mut packages = scan_pnp_cache(project_path)?;
+    packages.extend(
+        unplugged_dirs
+            .par_iter()
+            .filter_map(scan_package_files)
+            .collect::<Vec<_>>(),
+    );
+
+    let mut root_files = Vec::new();
+    for relative_path in PNP_ROOT_FILES {
+        let absolute_path = project_path.join(relative_path);
+        if let Ok(metadata) = fs::metadata(&absolute_path) {
+            if metadata.is_file() {
+                root_files.push(file_entry(
+                    absolute_path,
+                    relative_path.to_string(),
+                    &metadata,
+                ));
+            }
+        }
+    }
+    packages.extend(root_package(root_files));
+
+    let total_files: usize = packages.iter().map(|p| p.files.len()).sum();
+    let total_size: u64 = packages
+        .iter()
+        .flat_map(|p| p.files.iter())
+        .map(|f| f.size)
+        .sum();
+
+    if let Some(progress) = on_progress {
+        progress(unplugged_dirs.len(), unplugged_dirs.len(), "Done");
+    }
+
+    Ok(ScanResult {
+        layout: Layout::YarnPnp,
         packages,
         total_files,
         total_size,
@@ -254,3 +692,169 @@ pub fn count_files(node_modules_path: &Path) -> Result<usize> {
         .count();
     Ok(count)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scan_root_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let node_modules = dir.path();
+        fs::create_dir_all(node_modules.join(".pnpm/lodash@4.17.21/node_modules/lodash")).unwrap();
+        fs::write(node_modules.join(".modules.yaml"), "layoutVersion: 5").unwrap();
+        fs::write(
+            node_modules.join(".pnpm/lock.yaml"),
+            "lockfileVersion: '9.0'",
+        )
+        .unwrap();
+
+        let root = scan_root_files(node_modules).unwrap().unwrap();
+        let mut paths: Vec<&str> = root
+            .files
+            .iter()
+            .map(|f| f.relative_path.as_str())
+            .collect();
+        paths.sort();
+
+        assert_eq!(root.info.name, ROOT_PACKAGE_NAME);
+        assert_eq!(root.info.path, "");
+        assert_eq!(paths, vec![".modules.yaml", ".pnpm/lock.yaml"]);
+    }
+
+    #[test]
+    fn test_scan_package_without_package_json() {
+        let dir = tempfile::tempdir().unwrap();
+        let pkg_path = dir.path().join("broken");
+        fs::create_dir_all(&pkg_path).unwrap();
+        fs::write(pkg_path.join("index.js"), "module.exports = 1").unwrap();
+
+        let pkg = scan_package_files(&PackageDir {
+            path: pkg_path,
+            relative_path: ".pnpm/broken@1.0.0/node_modules/broken".to_string(),
+        })
+        .unwrap();
+
+        assert_eq!(pkg.info.name, "broken");
+        assert_eq!(pkg.files.len(), 1);
+        assert_eq!(pkg.anonymous_reason.as_deref(), Some("no package.json"));
+    }
+
+    #[test]
+    fn test_parse_package_json_manifest() {
+        let dir = tempfile::tempdir().unwrap();
+        let pkg_json = dir.path().join("package.json");
+        fs::write(
+            &pkg_json,
+            r#"{"name":"@scope/cli","version":"1.0.0","bin":"./bin/cli.js",
+                "dependencies":{"chalk":"^5.0.0"},
+                "peerDependencies":{"react":">=17"},
+                "optionalDependencies":{"fsevents":"~2.3.0"},
+                "devDependencies":{"jest":"29"}}"#,
+        )
+        .unwrap();
+
+        let manifest = parse_package_json(&pkg_json).unwrap();
+        let deps: Vec<(&str, &str, DependencyKind)> = manifest
+            .dependencies
+            .iter()
+            .map(|d| (d.name.as_str(), d.spec.as_str(), d.kind))
+            .collect();
+
+        assert_eq!(
+            deps,
+            vec![
+                ("chalk", "^5.0.0", DependencyKind::Dependencies),
+                ("react", ">=17", DependencyKind::PeerDependencies),
+                ("fsevents", "~2.3.0", DependencyKind::OptionalDependencies),
+            ]
+        );
+        assert_eq!(
+            manifest.bins,
+            vec![BinEntry {
+                name: "cli".to_string(),
+                path: "bin/cli.js".to_string()
+            }]
+        );
+    }
+
+    #[test]
+    fn test_parse_cache_zip_name() {
+        assert_eq!(
+            parse_cache_zip_name("lodash-npm-4.17.21-6382451519-c2dc4ec1e5.zip"),
+            ("lodash".to_string(), "4.17.21".to_string())
+        );
+        assert_eq!(
+            parse_cache_zip_name("@babel-core-npm-7.24.0-0a1b2c3d4e-e5f6a7b8c9.zip"),
+            ("@babel/core".to_string(), "7.24.0".to_string())
+        );
+        assert_eq!(
+            parse_cache_zip_name("typescript-npm-5.0.0-beta.1-0a1b2c3d4e-e5f6a7b8c9.zip"),
+            ("typescript".to_string(), "5.0.0-beta.1".to_string())
+        );
+        assert_eq!(
+            parse_cache_zip_name("my-pkg-patch-abc.zip"),
+            ("my-pkg-patch-abc".to_string(), "0.0.0".to_string())
+        );
+    }
+
+    #[test]
+    fn test_scan_yarn_pnp() {
+        let dir = tempfile::tempdir().unwrap();
+        let project = dir.path();
+        fs::create_dir_all(project.join(".yarn/cache")).unwrap();
+        fs::create_dir_all(
+            project.join(".yarn/unplugged/esbuild-npm-0.20.0-abc/node_modules/esbuild"),
+        )
+        .unwrap();
+        fs::write(project.join(".pnp.cjs"), "// pnp").unwrap();
+        fs::write(project.join(".yarn/install-state.gz"), "state").unwrap();
+        fs::write(
+            project.join(".yarn/cache/lodash-npm-4.17.21-6382451519-c2dc4ec1e5.zip"),
+            "zip",
+        )
+        .unwrap();
+        fs::write(
+            project
+                .join(".yarn/unplugged/esbuild-npm-0.20.0-abc/node_modules/esbuild/package.json"),
+            r#"{"name":"esbuild","version":"0.20.0"}"#,
+        )
+        .unwrap();
+
+        assert!(is_yarn_pnp_structure(project));
+        let result = scan_yarn_pnp(project, None).unwrap();
+
+        assert_eq!(result.layout, Layout::YarnPnp);
+        assert_eq!(result.packages.len(), 3);
+        assert_eq!(result.total_files, 4);
+
+        let lodash = result
+            .packages
+            .iter()
+            .find(|p| p.info.name == "lodash")
+            .unwrap();
+        assert_eq!(lodash.info.path, ".yarn/cache");
+        assert_eq!(
+            lodash.files[0].relative_path,
+            "lodash-npm-4.17.21-6382451519-c2dc4ec1e5.zip"
+        );
+
+        let esbuild = result
+            .packages
+            .iter()
+            .find(|p| p.info.name == "esbuild")
+            .unwrap();
+        assert_eq!(
+            esbuild.info.path,
+            ".yarn/unplugged/esbuild-npm-0.20.0-abc/node_modules/esbuild"
+        );
+    }
+
+    #[test]
+    fn test_scan_root_files_none() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir_all(dir.path().join("lodash")).unwrap();
+
+        assert!(scan_root_files(dir.path()).unwrap().is_none());
+    }
+}
@@ -1,9 +1,13 @@
 use anyhow::Result;
-use rusqlite::{params, Connection, Transaction};
+use rusqlite::{params, Connection, OpenFlags, OptionalExtension, Transaction};
 
-use crate::types::{BlobInfo, BlobStats, FileRecord, FileRecordWithPath, PackageInfo};
+use crate::types::{
+    BinEntry, BinRecord, BlobInfo, BlobStats, CodecStats, Dependency, DependencyKind,
+    DependencyRecord, DirectoryRecord, FileListing, FileRecord, FileRecordWithPath, PackageInfo,
+    PackageStats, RootDependency,
+};
 
-const SCHEMA_VERSION: &str = "1";
+const SCHEMA_VERSION: &str = "4";
 
 const CREATE_TABLES_SQL: &str = "
 CREATE TABLE IF NOT EXISTS metadata (
@@ -36,7 +40,40 @@ CREATE TABLE IF NOT EXISTS files (
   UNIQUE(package_id, relative_path)
 );
 
+CREATE TABLE IF NOT EXISTS directories (
+  id INTEGER PRIMARY KEY AUTOINCREMENT,
+  package_id INTEGER REFERENCES packages(id),
+  relative_path TEXT NOT NULL,
+  mode INTEGER,
+  UNIQUE(package_id, relative_path)
+);
+
+CREATE TABLE IF NOT EXISTS dependencies (
+  package_id INTEGER REFERENCES packages(id),
+  name TEXT NOT NULL,
+  spec TEXT NOT NULL,
+  kind TEXT NOT NULL,
+  PRIMARY KEY(package_id, kind, name)
+);
+
+CREATE TABLE IF NOT EXISTS root_dependencies (
+  importer TEXT NOT NULL,
+  name TEXT NOT NULL,
+  spec TEXT NOT NULL,
+  kind TEXT NOT NULL,
+  PRIMARY KEY(importer, kind, name)
+);
+
+CREATE TABLE IF NOT EXISTS bins (
+  package_id INTEGER REFERENCES packages(id),
+  name TEXT NOT NULL,
+  path TEXT NOT NULL,
+  PRIMARY KEY(package_id, name)
+);
+
+CREATE INDEX IF NOT EXISTS idx_packages_path ON packages(path);
 CREATE INDEX IF NOT EXISTS idx_files_package ON files(package_id);
+CREATE INDEX IF NOT EXISTS idx_dependencies_name ON dependencies(name);
 CREATE INDEX IF NOT EXISTS idx_files_blob ON files(blob_hash);
 ";
 
@@ -58,6 +95,20 @@ impl Store {
         Ok(store)
     }
 
+    /// Opens an existing DB without writing to it: no journal mode switch,
+    /// no DDL, no metadata updates and no `-wal`/`-shm` side files.
+    pub fn open_readonly(db_path: &str) -> Result<Self> {
+        let uri = format!("file:{}?immutable=1", encode_uri_path(db_path));
+        let conn = Connection::open_with_flags(
+            uri,
+            OpenFlags::SQLITE_OPEN_READ_ONLY
+                | OpenFlags::SQLITE_OPEN_URI
+                | OpenFlags::SQLITE_OPEN_NO_MUTEX,
+        )?;
+
+        Ok(Store { conn })
+    }
+
     pub fn set_metadata(&self, key: &str, value: &str) -> Result<()> {
         self.conn.execute(
             "INSERT OR REPLACE INTO metadata (key, value) VALUES (?1, ?2)",
@@ -74,6 +125,19 @@ impl Store {
         Ok(result)
     }
 
+    pub fn get_all_metadata(&self) -> Result<Vec<(String, String)>> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT key, COALESCE(value, '') FROM metadata ORDER BY key")?;
+        let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+
+        let mut metadata = Vec::new();
+        for row in rows {
+            metadata.push(row?);
+        }
+        Ok(metadata)
+    }
+
     pub fn insert_package(&self, pkg: &PackageInfo) -> Result<i64> {
         let mut stmt = self.conn.prepare_cached(
             "INSERT INTO packages (name, version, path) VALUES (?1, ?2, ?3)
@@ -116,6 +180,18 @@ impl Store {
         Ok(result)
     }
 
+    /// A blob's uncompressed size, without reading its content.
+    pub fn get_blob_size(&self, hash: &str) -> Result<Option<u64>> {
+        let mut stmt = self
+            .conn
+            .prepare_cached("SELECT original_size FROM blobs WHERE hash = ?1")?;
+        let size = stmt
+            .query_row(params![hash], |row| row.get::<_, Option<i64>>(0))
+            .optional()?
+            .flatten();
+        Ok(size.map(|size| size as u64))
+    }
+
     pub fn get_blob_stats(&self) -> Result<BlobStats> {
         let mut stmt = self.conn.prepare(
             "SELECT COUNT(*) as count,
@@ -133,6 +209,47 @@ impl Store {
         Ok(stats)
     }
 
+    /// Blob counts per codec (`gzip`, or `raw` for anything else), told
+    /// apart by magic bytes. SQLite loads each blob in full to read them,
+    /// so this scans the whole blobs table.
+    pub fn get_codec_stats(&self) -> Result<Vec<CodecStats>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT CASE
+                      WHEN substr(content, 1, 2) = X'1f8b' THEN 'gzip'
+                      ELSE 'raw'
+                    END AS codec,
+                    COUNT(*), COALESCE(SUM(compressed_size), 0)
+             FROM blobs
+             GROUP BY codec
+             ORDER BY COUNT(*) DESC",
+        )?;
+        let rows = stmt.query_map([], |row| {
+            Ok(CodecStats {
+                codec: row.get(0)?,
+                blobs: row.get::<_, i64>(1)? as usize,
+                compressed_size: row.get::<_, i64>(2)? as u64,
+            })
+        })?;
+
+        let mut stats = Vec::new();
+        for row in rows {
+            stats.push(row?);
+        }
+        Ok(stats)
+    }
+
+    /// Total size of all files as restored, counting shared blobs every
+    /// time they're used.
+    pub fn get_logical_size(&self) -> Result<u64> {
+        let mut stmt = self.conn.prepare(
+            "SELECT COALESCE(SUM(b.original_size), 0)
+             FROM files f
+             JOIN blobs b ON b.hash = f.blob_hash",
+        )?;
+        let size: i64 = stmt.query_row([], |row| row.get(0))?;
+        Ok(size as u64)
+    }
+
     pub fn insert_file(&self, file: &FileRecord) -> Result<()> {
         let mut stmt = self.conn.prepare_cached(
             "INSERT INTO files (package_id, relative_path, blob_hash, mode, mtime)
@@ -179,6 +296,299 @@ impl Store {
         Ok(files)
     }
 
+    pub fn get_packages(&self) -> Result<Vec<PackageInfo>> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT id, name, version, path FROM packages ORDER BY path")?;
+        let rows = stmt.query_map([], |row| {
+            Ok(PackageInfo {
+                id: Some(row.get(0)?),
+                name: row.get(1)?,
+                version: row.get(2)?,
+                path: row.get(3)?,
+            })
+        })?;
+
+        let mut packages = Vec::new();
+        for row in rows {
+            packages.push(row?);
+        }
+        Ok(packages)
+    }
+
+    pub fn get_package_stats(&self) -> Result<Vec<PackageStats>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT p.name, p.version, p.path, COUNT(f.id),
+                    COALESCE(SUM(b.original_size), 0), COALESCE(SUM(b.compressed_size), 0)
+             FROM packages p
+             LEFT JOIN files f ON f.package_id = p.id
+             LEFT JOIN blobs b ON b.hash = f.blob_hash
+             GROUP BY p.id
+             ORDER BY p.path",
+        )?;
+        let rows = stmt.query_map([], |row| {
+            Ok(PackageStats {
+                name: row.get(0)?,
+                version: row.get(1)?,
+                path: row.get(2)?,
+                files: row.get::<_, i64>(3)? as usize,
+                original_size: row.get::<_, i64>(4)? as u64,
+                compressed_size: row.get::<_, i64>(5)? as u64,
+            })
+        })?;
+
+        let mut stats = Vec::new();
+        for row in rows {
+            stats.push(row?);
+        }
+        Ok(stats)
+    }
+
+    /// Files of one package with their blob sizes, ordered by path. Files
+    /// whose blob is missing are listed without sizes.
+    pub fn get_file_listing(&self, package_id: i64) -> Result<Vec<FileListing>> {
+        let mut stmt = self.conn.prepare_cached(
+            "SELECT f.relative_path, f.mode, b.original_size, b.compressed_size, f.blob_hash
+             FROM files f
+             LEFT JOIN blobs b ON b.hash = f.blob_hash
+             WHERE f.package_id = ?1
+             ORDER BY f.relative_path",
+        )?;
+        let rows = stmt.query_map(params![package_id], |row| {
+            Ok(FileListing {
+                relative_path: row.get(0)?,
+                mode: row.get::<_, u32>(1)?,
+                size: row.get::<_, Option<i64>>(2)?.map(|size| size as u64),
+                compressed_size: row.get::<_, Option<i64>>(3)?.map(|size| size as u64),
+                blob_hash: row.get(4)?,
+            })
+        })?;
+
+        let mut files = Vec::new();
+        for row in rows {
+            files.push(row?);
+        }
+        Ok(files)
+    }
+
+    pub fn get_files_for_packages(&self, package_ids: &[i64]) -> Result<Vec<FileRecordWithPath>> {
+        let mut stmt = self.conn.prepare_cached(
+            "SELECT f.id, f.package_id, f.relative_path, f.blob_hash, f.mode, f.mtime, p.path as package_path
+             FROM files f
+             JOIN packages p ON f.package_id = p.id
+             WHERE f.package_id = ?1",
+        )?;
+
+        let mut files = Vec::new();
+        for package_id in package_ids {
+            let rows = stmt.query_map(params![package_id], |row| {
+                Ok(FileRecordWithPath {
+                    record: FileRecord {
+                        id: Some(row.get::<_, i64>(0)?),
+                        package_id: row.get(1)?,
+                        relative_path: row.get(2)?,
+                        blob_hash: row.get(3)?,
+                        mode: row.get::<_, u32>(4)?,
+                        mtime: row.get(5)?,
+                    },
+                    package_path: row.get(6)?,
+                })
+            })?;
+            for row in rows {
+                files.push(row?);
+            }
+        }
+        Ok(files)
+    }
+
+    /// Looks up a file by its path in the snapshot
+    /// (`<package-path>/<relative-path>`), trying the longest package path
+    /// first so nested packages win over their parents.
+    ///
+    /// The package is resolved first and the file then found through
+    /// `files(package_id, relative_path)`. Snapshots packed before
+    /// `idx_packages_path` existed only scan `packages`, not every file.
+    pub fn find_file(&self, path: &str) -> Result<Option<FileRecordWithPath>> {
+        let path = path.trim_start_matches("./").trim_matches('/');
+        let mut packages = self
+            .conn
+            .prepare_cached("SELECT id FROM packages WHERE path = ?1")?;
+        let mut files = self.conn.prepare_cached(
+            "SELECT id, package_id, relative_path, blob_hash, mode, mtime
+             FROM files
+             WHERE package_id = ?1 AND relative_path = ?2",
+        )?;
+
+        let splits = path
+            .rmatch_indices('/')
+            .map(|(i, _)| (&path[..i], &path[i + 1..]))
+            .chain(std::iter::once(("", path)));
+
+        for (package_path, relative_path) in splits {
+            let package_ids = packages
+                .query_map(params![package_path], |row| row.get::<_, i64>(0))?
+                .collect::<rusqlite::Result<Vec<_>>>()?;
+
+            for package_id in package_ids {
+                let found = files
+                    .query_row(params![package_id, relative_path], |row| {
+                        Ok(FileRecord {
+                            id: Some(row.get::<_, i64>(0)?),
+                            package_id: row.get(1)?,
+                            relative_path: row.get(2)?,
+                            blob_hash: row.get(3)?,
+                            mode: row.get::<_, u32>(4)?,
+                            mtime: row.get(5)?,
+                        })
+                    })
+                    .optional()?;
+                if let Some(record) = found {
+                    return Ok(Some(FileRecordWithPath {
+                        record,
+                        package_path: package_path.to_string(),
+                    }));
+                }
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Returns recorded directories, optionally limited to some packages.
+    /// Snapshots created before directories were tracked have none.
+    pub fn get_directories(&self, package_ids: Option<&[i64]>) -> Result<Vec<DirectoryRecord>> {
+        if !self.has_table("directories")? {
+            return Ok(Vec::new());
+        }
+
+        let mut stmt = self.conn.prepare(
+            "SELECT d.package_id, p.path, d.relative_path, d.mode
+             FROM directories d
+             JOIN packages p ON d.package_id = p.id
+             WHERE ?1 IS NULL OR d.package_id IN (SELECT value FROM json_each(?1))",
+        )?;
+        let rows = stmt.query_map(params![package_ids_param(package_ids)], |row| {
+            Ok(DirectoryRecord {
+                package_id: row.get(0)?,
+                package_path: row.get(1)?,
+                relative_path: row.get(2)?,
+                mode: row.get::<_, u32>(3)?,
+            })
+        })?;
+
+        let mut directories = Vec::new();
+        for row in rows {
+            directories.push(row?);
+        }
+        Ok(directories)
+    }
+
+    /// Returns declared dependencies, optionally limited to some packages.
+    /// Snapshots created before manifests were recorded have none.
+    pub fn get_dependencies(&self, package_ids: Option<&[i64]>) -> Result<Vec<DependencyRecord>> {
+        if !self.has_table("dependencies")? {
+            return Ok(Vec::new());
+        }
+
+        let mut stmt = self.conn.prepare(
+            "SELECT package_id, name, spec, kind FROM dependencies
+             WHERE ?1 IS NULL OR package_id IN (SELECT value FROM json_each(?1))
+             ORDER BY package_id, kind, name",
+        )?;
+        let rows = stmt.query_map(params![package_ids_param(package_ids)], |row| {
+            Ok((
+                row.get::<_, i64>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, String>(2)?,
+                row.get::<_, String>(3)?,
+            ))
+        })?;
+
+        let mut dependencies = Vec::new();
+        for row in rows {
+            let (package_id, name, spec, kind) = row?;
+            let Some(kind) = DependencyKind::parse(&kind) else {
+                continue;
+            };
+            dependencies.push(DependencyRecord {
+                package_id,
+                dependency: Dependency { name, spec, kind },
+            });
+        }
+        Ok(dependencies)
+    }
+
+    /// Dependencies declared by the project's own package.json files.
+    pub fn get_root_dependencies(&self) -> Result<Vec<RootDependency>> {
+        if !self.has_table("root_dependencies")? {
+            return Ok(Vec::new());
+        }
+
+        let mut stmt = self.conn.prepare(
+            "SELECT importer, name, spec, kind FROM root_dependencies ORDER BY importer, kind, name",
+        )?;
+        let rows = stmt.query_map([], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, String>(2)?,
+                row.get::<_, String>(3)?,
+            ))
+        })?;
+
+        let mut dependencies = Vec::new();
+        for row in rows {
+            let (importer, name, spec, kind) = row?;
+            if let Some(kind) = DependencyKind::parse(&kind) {
+                dependencies.push(RootDependency {
+                    importer,
+                    dependency: Dependency { name, spec, kind },
+                });
+            }
+        }
+        Ok(dependencies)
+    }
+
+    pub fn get_bins(&self, package_ids: Option<&[i64]>) -> Result<Vec<BinRecord>> {
+        if !self.has_table("bins")? {
+            return Ok(Vec::new());
+        }
+
+        let mut stmt = self.conn.prepare(
+            "SELECT package_id, name, path FROM bins
+             WHERE ?1 IS NULL OR package_id IN (SELECT value FROM json_each(?1))
+             ORDER BY package_id, name",
+        )?;
+        let rows = stmt.query_map(params![package_ids_param(package_ids)], |row| {
+            Ok(BinRecord {
+                package_id: row.get(0)?,
+                bin: BinEntry {
+                    name: row.get(1)?,
+                    path: row.get(2)?,
+                },
+            })
+        })?;
+
+        let mut bins = Vec::new();
+        for row in rows {
+            bins.push(row?);
+        }
+        Ok(bins)
+    }
+
+    pub fn has_table(&self, name: &str) -> Result<bool> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = ?1")?;
+        Ok(stmt.exists(params![name])?)
+    }
+
+    pub fn get_package_count(&self) -> Result<usize> {
+        let mut stmt = self.conn.prepare("SELECT COUNT(*) FROM packages")?;
+        let count: i64 = stmt.query_row([], |row| row.get(0))?;
+        Ok(count as usize)
+    }
+
     pub fn get_total_file_count(&self) -> Result<usize> {
         let mut stmt = self.conn.prepare("SELECT COUNT(*) FROM files")?;
         let count: i64 = stmt.query_row([], |row| row.get(0))?;
@@ -195,3 +605,176 @@ impl Store {
         Ok(result)
     }
 }
+
+fn encode_uri_path(path: &str) -> String {
+    let path = path.replace('\\', "/");
+    let mut encoded = String::with_capacity(path.len());
+    for c in path.chars() {
+        match c {
+            '%' => encoded.push_str("%25"),
+            '?' => encoded.push_str("%3f"),
+            '#' => encoded.push_str("%23"),
+            _ => encoded.push(c),
+        }
+    }
+    encoded
+}
+
+/// Binds an optional package id filter for `json_each`; `None` becomes NULL
+/// and matches every package.
+fn package_ids_param(package_ids: Option<&[i64]>) -> Option<String> {
+    package_ids.map(|ids| serde_json::to_string(ids).unwrap_or_default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode_uri_path() {
+        assert_eq!(encode_uri_path("./node_modules.db"), "./node_modules.db");
+        assert_eq!(encode_uri_path("/tmp/a?b#c%d.db"), "/tmp/a%3fb%23c%25d.db");
+    }
+
+    #[test]
+    fn test_open_readonly_does_not_write() {
+        let dir = tempfile::tempdir().unwrap();
+        let db_path = dir.path().join("test.db");
+        let db = db_path.to_str().unwrap();
+
+        {
+            let store = Store::open(db).unwrap();
+            store.set_metadata("created_at", "now").unwrap();
+        }
+        let _ = std::fs::remove_file(dir.path().join("test.db-wal"));
+        let _ = std::fs::remove_file(dir.path().join("test.db-shm"));
+
+        let store = Store::open_readonly(db).unwrap();
+        assert_eq!(
+            store.get_metadata("created_at").unwrap().as_deref(),
+            Some("now")
+        );
+        assert!(store.set_metadata("created_at", "later").is_err());
+        assert!(!dir.path().join("test.db-wal").exists());
+        assert!(!dir.path().join("test.db-shm").exists());
+    }
+
+    #[test]
+    fn test_get_directories_filters_packages() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = Store::open(dir.path().join("test.db").to_str().unwrap()).unwrap();
+
+        let mut ids = Vec::new();
+        for name in ["a", "b"] {
+            let id = store
+                .insert_package(&PackageInfo {
+                    id: None,
+                    name: name.to_string(),
+                    version: "1.0.0".to_string(),
+                    path: name.to_string(),
+                })
+                .unwrap();
+            store
+                .conn
+                .execute(
+                    "INSERT INTO directories (package_id, relative_path, mode) VALUES (?1, 'lib', 493)",
+                    params![id],
+                )
+                .unwrap();
+            ids.push(id);
+        }
+
+        assert_eq!(store.get_directories(None).unwrap().len(), 2);
+        let selected = store.get_directories(Some(&ids[1..])).unwrap();
+        assert_eq!(selected.len(), 1);
+        assert_eq!(selected[0].package_path, "b");
+        assert!(store.get_directories(Some(&[])).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_get_package_stats() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = Store::open(dir.path().join("test.db").to_str().unwrap()).unwrap();
+
+        let mut ids = Vec::new();
+        for name in ["lodash", "empty"] {
+            ids.push(
+                store
+                    .insert_package(&PackageInfo {
+                        id: None,
+                        name: name.to_string(),
+                        version: "1.0.0".to_string(),
+                        path: name.to_string(),
+                    })
+                    .unwrap(),
+            );
+        }
+        store
+            .insert_blob(&BlobInfo {
+                hash: "h".to_string(),
+                content: vec![0; 4],
+                original_size: 10,
+                compressed_size: 4,
+            })
+            .unwrap();
+        for path in ["a.js", "b.js"] {
+            store
+                .insert_file(&FileRecord {
+                    id: None,
+                    package_id: ids[0],
+                    relative_path: path.to_string(),
+                    blob_hash: "h".to_string(),
+                    mode: 0o644,
+                    mtime: 0,
+                })
+                .unwrap();
+        }
+
+        let stats = store.get_package_stats().unwrap();
+        assert_eq!(stats.len(), 2);
+        assert_eq!(
+            (
+                stats[0].name.as_str(),
+                stats[0].files,
+                stats[0].original_size
+            ),
+            ("empty", 0, 0)
+        );
+        assert_eq!(
+            (
+                stats[1].files,
+                stats[1].original_size,
+                stats[1].compressed_size
+            ),
+            (2, 20, 8)
+        );
+        assert_eq!(
+            store.get_file_listing(ids[0]).unwrap()[1].relative_path,
+            "b.js"
+        );
+
+        let found = store.find_file("lodash/b.js").unwrap().unwrap();
+        assert_eq!(found.record.package_id, ids[0]);
+        assert_eq!(found.record.relative_path, "b.js");
+        assert!(store.find_file("lodash/c.js").unwrap().is_none());
+
+        // A damaged snapshot whose blob is gone.
+        store
+            .conn
+            .execute_batch("PRAGMA foreign_keys = OFF")
+            .unwrap();
+        store
+            .insert_file(&FileRecord {
+                id: None,
+                package_id: ids[0],
+                relative_path: "c.js".to_string(),
+                blob_hash: "gone".to_string(),
+                mode: 0o644,
+                mtime: 0,
+            })
+            .unwrap();
+        let listing = store.get_file_listing(ids[0]).unwrap();
+        assert_eq!(listing.len(), 3);
+        assert_eq!((listing[0].size, listing[2].size), (Some(10), None));
+    }
+}
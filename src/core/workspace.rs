@@ -0,0 +1,240 @@
+use anyhow::{Context, Result};
+use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
+use std::fs;
+use std::path::Path;
+use walkdir::WalkDir;
+
+use crate::core::scanner::{scan_node_modules, ScanResult};
+use crate::types::{Layout, ProgressFn};
+
+/// Workspace globs from the root `package.json` (`workspaces` as an array
+/// or as `{ "packages": [...] }`) and from `pnpm-workspace.yaml`.
+pub fn read_workspace_patterns(project_path: &Path) -> Result<Vec<String>> {
+    let mut patterns = Vec::new();
+
+    let pkg_json_path = project_path.join("package.json");
+    if pkg_json_path.exists() {
+        let content = fs::read_to_string(&pkg_json_path)?;
+        let parsed: serde_json::Value = serde_json::from_str(&content)
+            .with_context(|| format!("Invalid {}", pkg_json_path.display()))?;
+
+        let workspaces = match &parsed["workspaces"] {
+            serde_json::Value::Array(items) => items.clone(),
+            serde_json::Value::Object(obj) => obj
+                .get("packages")
+                .and_then(|p| p.as_array())
+                .cloned()
+                .unwrap_or_default(),
+            _ => Vec::new(),
+        };
+        patterns.extend(
+            workspaces
+                .iter()
+                .filter_map(|w| w.as_str())
+                .map(|w| w.to_string()),
+        );
+    }
+
+    let pnpm_workspace_path = project_path.join("pnpm-workspace.yaml");
+    if pnpm_workspace_path.exists() {
+        let content = fs::read_to_string(&pnpm_workspace_path)?;
+        patterns.extend(parse_pnpm_workspace_packages(&content));
+    }
+
+    Ok(patterns)
+}
+
+/// Reads the `packages:` list of a pnpm-workspace.yaml. Only the block
+/// sequence form pnpm documents is supported.
+fn parse_pnpm_workspace_packages(content: &str) -> Vec<String> {
+    let mut packages = Vec::new();
+    let mut in_packages = false;
+
+    for line in content.lines() {
+        let trimmed = line.trim();
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            continue;
+        }
+
+        if !line.starts_with([' ', '\t', '-']) {
+            in_packages = trimmed == "packages:";
+            continue;
+        }
+
+        if let (true, Some(item)) = (in_packages, trimmed.strip_prefix('-')) {
+            let item = item.split(" #").next().unwrap_or_default().trim();
+            let item = item.trim_matches(|c| c == '\'' || c == '"');
+            if !item.is_empty() {
+                packages.push(item.to_string());
+            }
+        }
+    }
+
+    packages
+}
+
+/// Workspace directories (relative to the project root) matching
+/// `patterns`; `!`-prefixed patterns exclude.
+pub fn find_workspace_dirs(project_path: &Path, patterns: &[String]) -> Result<Vec<String>> {
+    let mut include = GlobSetBuilder::new();
+    let mut exclude = GlobSetBuilder::new();
+    for pattern in patterns {
+        let (builder, pattern) = match pattern.strip_prefix('!') {
+            Some(negated) => (&mut exclude, negated),
+            None => (&mut include, pattern.as_str()),
+        };
+        let pattern = pattern.trim_start_matches("./").trim_end_matches('/');
+        let glob = GlobBuilder::new(pattern)
+            .literal_separator(true)
+            .build()
+            .with_context(|| format!("Invalid workspace pattern: {}", pattern))?;
+        builder.add(glob);
+    }
+    let include: GlobSet = include.build()?;
+    let exclude: GlobSet = exclude.build()?;
+
+    let mut dirs = Vec::new();
+    let walker = WalkDir::new(project_path)
+        .min_depth(1)
+        .into_iter()
+        .filter_entry(|e| {
+            let name = e.file_name().to_string_lossy();
+            e.file_type().is_dir() && name != "node_modules" && !name.starts_with('.')
+        });
+
+    for entry in walker.filter_map(|e| e.ok()) {
+        let relative = entry
+            .path()
+            .strip_prefix(project_path)?
+            .to_string_lossy()
+            .replace('\\', "/");
+
+        if include.is_match(&relative)
+            && !exclude.is_match(&relative)
+            && entry.path().join("package.json").exists()
+        {
+            dirs.push(relative);
+        }
+    }
+
+    dirs.sort();
+    Ok(dirs)
+}
+
+/// Snapshots the root node_modules and every workspace's node_modules into
+/// one result, with package paths prefixed by their node_modules location
+/// (`node_modules/lodash`, `packages/app/node_modules/react`).
+pub fn scan_workspace(
+    project_path: &Path,
+    on_progress: Option<&ProgressFn<'_>>,
+) -> Result<(ScanResult, Vec<String>)> {
+    let patterns = read_workspace_patterns(project_path)?;
+    let workspace_dirs = find_workspace_dirs(project_path, &patterns)?;
+
+    let mut locations = vec!["node_modules".to_string()];
+    locations.extend(workspace_dirs.iter().map(|d| format!("{}/node_modules", d)));
+    locations.retain(|loc| project_path.join(loc).is_dir());
+
+    let mut result = ScanResult {
+        layout: Layout::Workspace,
+        packages: Vec::new(),
+        total_files: 0,
+        total_size: 0,
+    };
+
+    for location in &locations {
+        let scanned = scan_node_modules(&project_path.join(location), on_progress)?;
+        result.total_files += scanned.total_files;
+        result.total_size += scanned.total_size;
+
+        for mut pkg in scanned.packages {
+            pkg.info.path = if pkg.info.path.is_empty() {
+                location.clone()
+            } else {
+                format!("{}/{}", location, pkg.info.path)
+            };
+            result.packages.push(pkg);
+        }
+    }
+
+    Ok((result, locations))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_pnpm_workspace_packages() {
+        let yaml = "packages:\n  - 'packages/*'\n  - \"apps/**\" # apps\n  - '!**/test/**'\ncatalog:\n  react: ^18\n";
+        assert_eq!(
+            parse_pnpm_workspace_packages(yaml),
+            vec!["packages/*", "apps/**", "!**/test/**"]
+        );
+    }
+
+    #[test]
+    fn test_find_workspace_dirs() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        for ws in ["packages/a", "packages/b", "packages/test-utils", "docs"] {
+            fs::create_dir_all(root.join(ws)).unwrap();
+            fs::write(root.join(ws).join("package.json"), "{}").unwrap();
+        }
+        fs::create_dir_all(root.join("packages/no-manifest")).unwrap();
+        fs::write(
+            root.join("package.json"),
+            r#"{"workspaces": ["packages/*", "!packages/test-*"]}"#,
+        )
+        .unwrap();
+
+        let patterns = read_workspace_patterns(root).unwrap();
+        let dirs = find_workspace_dirs(root, &patterns).unwrap();
+
+        assert_eq!(dirs, vec!["packages/a", "packages/b"]);
+    }
+
+    #[test]
+    fn test_scan_workspace_prefixes_locations() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        fs::write(
+            root.join("package.json"),
+            r#"{"workspaces": {"packages": ["packages/*"]}}"#,
+        )
+        .unwrap();
+        fs::create_dir_all(root.join("packages/a/node_modules/left-pad")).unwrap();
+        fs::write(root.join("packages/a/package.json"), "{}").unwrap();
+        fs::write(
+            root.join("packages/a/node_modules/left-pad/package.json"),
+            r#"{"name":"left-pad","version":"1.3.0"}"#,
+        )
+        .unwrap();
+        fs::create_dir_all(root.join("node_modules/lodash")).unwrap();
+        fs::write(
+            root.join("node_modules/lodash/package.json"),
+            r#"{"name":"lodash","version":"4.17.21"}"#,
+        )
+        .unwrap();
+        fs::write(root.join("node_modules/.package-lock.json"), "{}").unwrap();
+
+        let (result, locations) = scan_workspace(root, None).unwrap();
+        let mut paths: Vec<&str> = result
+            .packages
+            .iter()
+            .map(|p| p.info.path.as_str())
+            .collect();
+        paths.sort();
+
+        assert_eq!(locations, vec!["node_modules", "packages/a/node_modules"]);
+        assert_eq!(
+            paths,
+            vec![
+                "node_modules",
+                "node_modules/lodash",
+                "packages/a/node_modules/left-pad"
+            ]
+        );
+        assert_eq!(result.layout, Layout::Workspace);
+    }
+}
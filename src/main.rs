@@ -1,7 +1,4 @@
-mod commands;
-mod core;
-mod types;
-mod utils;
+use mohyung::{commands, types};
 
 use clap::{Parser, Subcommand};
 
@@ -31,6 +28,36 @@ enum Commands {
 
         #[arg(long)]
         include_lockfile: bool,
+
+        /// Print a JSON summary to stdout
+        #[arg(long)]
+        json: bool,
+
+        /// Snapshot the root and every workspace's node_modules (from package.json
+        /// "workspaces" or pnpm-workspace.yaml)
+        #[arg(short = 'w', long)]
+        workspaces: bool,
+
+        /// Leave out files matching a glob, relative to each package (repeatable)
+        #[arg(short = 'e', long = "exclude", value_name = "GLOB")]
+        excludes: Vec<String>,
+
+        /// Drop docs, tests and source maps (*.md, test/, __tests__/, *.map, docs/, .github/)
+        #[arg(long)]
+        prune: bool,
+
+        /// Only snapshot packages needed at runtime, leaving out devDependencies
+        #[arg(long)]
+        production: bool,
+
+        /// Build from package-lock.json and tarballs in the npm cache, offline,
+        /// instead of an installed node_modules
+        #[arg(long)]
+        from_cache: bool,
+
+        /// npm cache directory [default: $npm_config_cache or ~/.npm]
+        #[arg(long, value_name = "DIR", requires = "from_cache")]
+        cache: Option<String>,
     },
 
     /// Restore node_modules from SQLite DB
@@ -38,11 +65,32 @@ enum Commands {
         #[arg(short = 'i', long, default_value = "./node_modules.db")]
         input: String,
 
-        #[arg(short = 'o', long, default_value = "./node_modules")]
-        output: String,
+        /// Output directory [default: ./node_modules, or . for Yarn PnP and workspace snapshots]
+        #[arg(short = 'o', long)]
+        output: Option<String>,
 
         #[arg(short = 'f', long)]
         force: bool,
+
+        /// Only write files that differ from the existing output and delete untracked ones
+        #[arg(long, conflicts_with = "force")]
+        sync: bool,
+
+        /// Only restore matching packages: <name[@range]>, globs allowed (repeatable)
+        #[arg(short = 'p', long = "package", value_name = "SPEC")]
+        packages: Vec<String>,
+
+        /// How to materialize files that share the same content
+        #[arg(long, value_enum, default_value = "copy")]
+        link_mode: types::LinkMode,
+
+        /// Leave restored files with the current time instead of the snapshot's mtimes
+        #[arg(long, conflicts_with = "mtime_epoch")]
+        no_mtime: bool,
+
+        /// Set every restored file's mtime to this Unix timestamp (seconds)
+        #[arg(long, value_name = "SECONDS")]
+        mtime_epoch: Option<i64>,
     },
 
     /// Compare DB with current node_modules
@@ -50,8 +98,116 @@ enum Commands {
         #[arg(long, default_value = "./node_modules.db")]
         db: String,
 
-        #[arg(short = 'n', long, default_value = "./node_modules")]
-        node_modules: String,
+        /// Directory to compare [default: ./node_modules, or . for Yarn PnP and workspace snapshots]
+        #[arg(short = 'n', long)]
+        node_modules: Option<String>,
+
+        /// Summarize changes per package, including version mismatches
+        #[arg(long)]
+        packages: bool,
+
+        /// Print unified diffs of modified text files against the DB
+        #[arg(long)]
+        diff: bool,
+    },
+
+    /// Print a file from the DB to stdout
+    Cat {
+        /// DB file path
+        db: String,
+
+        /// <package-path>/<relative-path>, e.g. lodash/package.json
+        path: String,
+    },
+
+    /// Compare two DBs: added, removed and upgraded packages and changed files
+    Diff {
+        /// Old DB file path
+        old: String,
+
+        /// New DB file path
+        new: String,
+
+        /// Show unified diffs of modified text files
+        #[arg(long)]
+        patch: bool,
+    },
+
+    /// Write the DB's files to a tar archive without extracting them
+    Export {
+        #[arg(long, default_value = "./node_modules.db")]
+        db: String,
+
+        /// Archive path, or - for stdout [default: ./node_modules.<format>]
+        #[arg(short = 'o', long)]
+        output: Option<String>,
+
+        #[arg(short = 'f', long, value_enum, default_value = "tar")]
+        format: types::ExportFormat,
+
+        /// Directory to put entries under [default: node_modules, or none for Yarn PnP and workspace snapshots]
+        #[arg(long)]
+        prefix: Option<String>,
+    },
+
+    /// Pack a node_modules tarball (tar, tar.gz, tar.zst) or directory into a DB
+    Import {
+        /// Archive or directory to import
+        input: String,
+
+        #[arg(short = 'o', long, default_value = "./node_modules.db")]
+        output: String,
+
+        #[arg(short = 'c', long, default_value = "6")]
+        compression: u32,
+
+        #[arg(long)]
+        include_lockfile: bool,
+    },
+
+    /// Show DB metadata and statistics
+    Info {
+        #[arg(long, default_value = "./node_modules.db")]
+        db: String,
+    },
+
+    /// List packages, or a package's files, in the DB
+    Ls {
+        #[arg(long, default_value = "./node_modules.db")]
+        db: String,
+
+        /// List the files of packages matching <name[@range]> instead
+        #[arg(long, value_name = "PACKAGE")]
+        files: Option<String>,
+
+        #[arg(long, value_enum, default_value = "name")]
+        sort: types::LsSort,
+
+        /// Print JSON to stdout
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Explain why a package is in the snapshot
+    Why {
+        /// Package to explain: <name[@range]>, globs allowed
+        package: String,
+
+        #[arg(long, default_value = "./node_modules.db")]
+        db: String,
+    },
+
+    /// Check packages against their pristine tarballs in the npm cache
+    Verify {
+        #[arg(long, default_value = "./node_modules.db")]
+        db: String,
+
+        #[arg(long, default_value = "./package-lock.json")]
+        lockfile: String,
+
+        /// npm cache directory [default: $npm_config_cache or ~/.npm]
+        #[arg(long, value_name = "DIR")]
+        cache: Option<String>,
     },
 }
 
@@ -64,24 +220,105 @@ fn main() {
             source,
             compression,
             include_lockfile,
+            json,
+            workspaces,
+            excludes,
+            prune,
+            production,
+            from_cache,
+            cache,
         } => commands::pack::pack(&types::PackOptions {
             output,
             source,
             compression_level: compression,
             include_lockfile,
+            json,
+            workspaces,
+            excludes,
+            prune,
+            production,
+            from_cache,
+            cache,
         }),
         Commands::Unpack {
             input,
             output,
             force,
+            sync,
+            packages,
+            link_mode,
+            no_mtime,
+            mtime_epoch,
         } => commands::unpack::unpack(&types::UnpackOptions {
             input,
             output,
             force,
+            sync,
+            packages,
+            link_mode,
+            restore_mtime: !no_mtime,
+            mtime_epoch,
         }),
-        Commands::Status { db, node_modules } => {
-            commands::status::status(&db, &node_modules).map(|_| ())
+        Commands::Status {
+            db,
+            node_modules,
+            packages,
+            diff,
+        } => commands::status::status(&types::StatusOptions {
+            db,
+            node_modules,
+            packages,
+            diff,
+        })
+        .map(|_| ()),
+        Commands::Cat { db, path } => commands::cat::cat(&db, &path),
+        Commands::Diff { old, new, patch } => {
+            commands::diff::diff(&types::DiffOptions { old, new, patch })
         }
+        Commands::Export {
+            db,
+            output,
+            format,
+            prefix,
+        } => commands::export::export(&types::ExportOptions {
+            db,
+            output,
+            format,
+            prefix,
+        }),
+        Commands::Import {
+            input,
+            output,
+            compression,
+            include_lockfile,
+        } => commands::import::import(&types::ImportOptions {
+            input,
+            output,
+            compression_level: compression,
+            include_lockfile,
+        }),
+        Commands::Info { db } => commands::info::info(&db),
+        Commands::Ls {
+            db,
+            files,
+            sort,
+            json,
+        } => commands::ls::ls(&types::LsOptions {
+            db,
+            files,
+            sort,
+            json,
+        }),
+        Commands::Why { package, db } => commands::why::why(&db, &package),
+        Commands::Verify {
+            db,
+            lockfile,
+            cache,
+        } => commands::verify::verify(&types::VerifyOptions {
+            db,
+            lockfile,
+            cache,
+        }),
     };
 
     if let Err(e) = result {
@@ -1,5 +1,6 @@
 #![allow(dead_code)]
 
+use serde::Serialize;
 use std::path::PathBuf;
 
 #[derive(Debug, Clone)]
@@ -11,6 +12,12 @@ pub struct FileEntry {
     pub mtime: i64,
 }
 
+#[derive(Debug, Clone)]
+pub struct DirectoryEntry {
+    pub relative_path: String,
+    pub mode: u32,
+}
+
 #[derive(Debug, Clone)]
 pub struct PackageInfo {
     pub id: Option<i64>,
@@ -19,6 +26,83 @@ pub struct PackageInfo {
     pub path: String,
 }
 
+/// Which package.json field a dependency was declared in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DependencyKind {
+    Dependencies,
+    PeerDependencies,
+    OptionalDependencies,
+    /// Only recorded for the project's own manifests.
+    DevDependencies,
+}
+
+impl DependencyKind {
+    pub const ALL: [DependencyKind; 4] = [
+        DependencyKind::Dependencies,
+        DependencyKind::PeerDependencies,
+        DependencyKind::OptionalDependencies,
+        DependencyKind::DevDependencies,
+    ];
+
+    /// Fields that matter for an installed package.
+    pub const INSTALLED: [DependencyKind; 3] = [
+        DependencyKind::Dependencies,
+        DependencyKind::PeerDependencies,
+        DependencyKind::OptionalDependencies,
+    ];
+
+    /// The package.json field name, also used as the stored value.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DependencyKind::Dependencies => "dependencies",
+            DependencyKind::PeerDependencies => "peerDependencies",
+            DependencyKind::OptionalDependencies => "optionalDependencies",
+            DependencyKind::DevDependencies => "devDependencies",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|kind| kind.as_str() == value)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Dependency {
+    pub name: String,
+    /// Version range or other specifier (`^1.2.0`, `npm:foo@1`, `workspace:*`).
+    pub spec: String,
+    pub kind: DependencyKind,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BinEntry {
+    pub name: String,
+    /// Script path relative to the package directory.
+    pub path: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct DependencyRecord {
+    pub package_id: i64,
+    pub dependency: Dependency,
+}
+
+/// A dependency declared by the project itself (or, in a workspace snapshot,
+/// by one of its workspaces).
+#[derive(Debug, Clone)]
+pub struct RootDependency {
+    /// Project-relative directory of the declaring package.json; empty for
+    /// the project root.
+    pub importer: String,
+    pub dependency: Dependency,
+}
+
+#[derive(Debug, Clone)]
+pub struct BinRecord {
+    pub package_id: i64,
+    pub bin: BinEntry,
+}
+
 #[derive(Debug, Clone)]
 pub struct BlobInfo {
     pub hash: String,
@@ -43,19 +127,167 @@ pub struct FileRecordWithPath {
     pub package_path: String,
 }
 
+impl FileRecordWithPath {
+    /// Path of the file relative to node_modules.
+    pub fn node_modules_path(&self) -> String {
+        if self.package_path.is_empty() {
+            self.record.relative_path.clone()
+        } else {
+            format!("{}/{}", self.package_path, self.record.relative_path)
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct DirectoryRecord {
+    pub package_id: i64,
+    pub package_path: String,
+    pub relative_path: String,
+    pub mode: u32,
+}
+
+/// How paths in a snapshot are rooted, stored as the `layout` metadata key.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Layout {
+    /// Package paths are relative to a node_modules directory (npm, yarn, pnpm).
+    #[default]
+    NodeModules,
+    /// Yarn Plug'n'Play: paths are relative to the project root.
+    YarnPnp,
+    /// Every node_modules of a monorepo: paths are relative to the project
+    /// root and start with their node_modules location.
+    Workspace,
+}
+
+impl Layout {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Layout::NodeModules => "node_modules",
+            Layout::YarnPnp => "yarn-pnp",
+            Layout::Workspace => "workspace",
+        }
+    }
+
+    /// Snapshots without the key predate layouts and are node_modules-rooted.
+    pub fn from_metadata(value: Option<&str>) -> Self {
+        match value {
+            Some("yarn-pnp") => Layout::YarnPnp,
+            Some("workspace") => Layout::Workspace,
+            _ => Layout::NodeModules,
+        }
+    }
+
+    /// Whether paths are relative to the project root instead of node_modules.
+    pub fn is_project_rooted(&self) -> bool {
+        !matches!(self, Layout::NodeModules)
+    }
+
+    /// node_modules directories relative to the snapshot root, given the
+    /// `locations` metadata (a JSON array) recorded for workspace snapshots.
+    pub fn node_modules_roots(&self, locations: Option<&str>) -> Vec<String> {
+        match self {
+            Layout::NodeModules => vec![String::new()],
+            Layout::YarnPnp => Vec::new(),
+            Layout::Workspace => locations
+                .and_then(|l| serde_json::from_str(l).ok())
+                .unwrap_or_default(),
+        }
+    }
+
+    pub fn default_path(&self) -> &'static str {
+        if self.is_project_rooted() {
+            "."
+        } else {
+            "./node_modules"
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct PackOptions {
     pub output: String,
     pub source: String,
     pub compression_level: u32,
     pub include_lockfile: bool,
+    pub json: bool,
+    pub workspaces: bool,
+    /// Extra exclude globs on top of the project's `.mohyungignore`.
+    pub excludes: Vec<String>,
+    pub prune: bool,
+    pub production: bool,
+    /// Build from package-lock.json and the npm cache instead of node_modules.
+    pub from_cache: bool,
+    /// npm cache directory, for `from_cache`.
+    pub cache: Option<String>,
 }
 
 #[derive(Debug, Clone)]
-pub struct UnpackOptions {
+pub struct ImportOptions {
     pub input: String,
     pub output: String,
+    pub compression_level: u32,
+    pub include_lockfile: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SkippedDir {
+    pub path: String,
+    pub reason: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PackSummary {
+    pub output: String,
+    pub packages: usize,
+    pub files: usize,
+    pub original_size: u64,
+    pub db_size: u64,
+    pub deduplicated: usize,
+    pub excluded_files: usize,
+    pub excluded_size: u64,
+    /// Packages left out by `--production`.
+    pub dev_packages: usize,
+    /// Directories without a usable package.json, stored as anonymous packages.
+    pub anonymous_packages: Vec<SkippedDir>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum LinkMode {
+    #[default]
+    Copy,
+    Hardlink,
+    Reflink,
+}
+
+#[derive(Debug, Clone)]
+pub struct ExtractOptions {
+    pub link_mode: LinkMode,
+    pub restore_mtime: bool,
+    /// Overrides every file's mtime (milliseconds since the epoch).
+    pub fixed_mtime: Option<i64>,
+}
+
+impl Default for ExtractOptions {
+    fn default() -> Self {
+        ExtractOptions {
+            link_mode: LinkMode::Copy,
+            restore_mtime: true,
+            fixed_mtime: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct UnpackOptions {
+    pub input: String,
+    /// Defaults to the snapshot layout's usual location.
+    pub output: Option<String>,
     pub force: bool,
+    pub sync: bool,
+    pub packages: Vec<String>,
+    pub link_mode: LinkMode,
+    pub restore_mtime: bool,
+    pub mtime_epoch: Option<i64>,
 }
 
 #[derive(Debug, Clone, Default)]
@@ -66,6 +298,98 @@ pub struct StatusResult {
     pub unchanged: usize,
 }
 
+#[derive(Debug, Clone)]
+pub struct StatusOptions {
+    pub db: String,
+    pub node_modules: Option<String>,
+    pub packages: bool,
+    pub diff: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PackageState {
+    /// Installed, but files or the version differ from the snapshot.
+    Changed,
+    /// In the snapshot, but none of its files are installed.
+    Missing,
+    /// Installed, but not in the snapshot.
+    Untracked,
+}
+
+/// `status` results aggregated by package.
+#[derive(Debug, Clone)]
+pub struct PackageStatus {
+    pub name: String,
+    /// Recorded version, or the installed one for untracked packages.
+    pub version: String,
+    pub path: String,
+    pub state: PackageState,
+    /// Installed version, when it differs from the recorded one.
+    pub installed_version: Option<String>,
+    pub modified: usize,
+    pub deleted: usize,
+    pub untracked: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct DiffOptions {
+    pub old: String,
+    pub new: String,
+    /// Print unified diffs of modified text files.
+    pub patch: bool,
+}
+
+/// Sort order for `ls`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum LsSort {
+    #[default]
+    Name,
+    Path,
+    /// Largest first.
+    Size,
+    /// Most files first. Only applies to the package list.
+    Files,
+}
+
+#[derive(Debug, Clone)]
+pub struct LsOptions {
+    pub db: String,
+    /// List the files of packages matching this spec instead of packages.
+    pub files: Option<String>,
+    pub sort: LsSort,
+    pub json: bool,
+}
+
+/// A package with the totals of its files. Sizes count a shared blob once
+/// for every file that uses it.
+#[derive(Debug, Clone, Serialize)]
+pub struct PackageStats {
+    pub name: String,
+    pub version: String,
+    pub path: String,
+    pub files: usize,
+    pub original_size: u64,
+    pub compressed_size: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FileListing {
+    pub relative_path: String,
+    pub mode: u32,
+    /// `None` when the file's blob is missing from the DB.
+    pub size: Option<u64>,
+    pub compressed_size: Option<u64>,
+    pub blob_hash: String,
+}
+
+/// Blobs stored with one codec, detected from the content's magic bytes.
+#[derive(Debug, Clone)]
+pub struct CodecStats {
+    pub codec: String,
+    pub blobs: usize,
+    pub compressed_size: u64,
+}
+
 #[derive(Debug, Clone)]
 pub struct BlobStats {
     pub total_blobs: usize,
@@ -73,4 +397,51 @@ pub struct BlobStats {
     pub total_compressed_size: u64,
 }
 
+pub type ProgressFn<'a> = dyn Fn(usize, usize, &str) + Sync + 'a;
+
 pub type ProgressCallback = Box<dyn Fn(usize, usize, Option<&str>) + Send + Sync>;
+
+#[derive(Debug, Clone)]
+pub struct VerifyOptions {
+    pub db: String,
+    pub lockfile: String,
+    /// npm cache directory.
+    pub cache: Option<String>,
+}
+
+/// Archive format for `export`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum ExportFormat {
+    #[default]
+    Tar,
+    #[value(name = "tar.gz")]
+    TarGz,
+    #[value(name = "tar.zst")]
+    TarZst,
+}
+
+impl ExportFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Tar => "tar",
+            ExportFormat::TarGz => "tar.gz",
+            ExportFormat::TarZst => "tar.zst",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ExportOptions {
+    pub db: String,
+    pub output: Option<String>,
+    pub format: ExportFormat,
+    pub prefix: Option<String>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ExportSummary {
+    pub directories: usize,
+    pub files: usize,
+    pub symlinks: usize,
+    pub total_size: u64,
+}
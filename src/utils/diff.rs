@@ -0,0 +1,75 @@
+use similar::TextDiff;
+
+/// How much of a file is checked for NUL bytes to tell binary from text.
+const BINARY_SNIFF_LEN: usize = 8000;
+
+/// Files larger than this (either side) aren't diffed.
+pub const MAX_DIFF_SIZE: usize = 1024 * 1024;
+
+/// What to show for a modified file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FileDiff {
+    Text(String),
+    Binary,
+    TooLarge,
+}
+
+/// Binary if it has a NUL byte near the start or isn't valid UTF-8.
+pub fn is_binary(content: &[u8]) -> bool {
+    content[..content.len().min(BINARY_SNIFF_LEN)].contains(&0)
+        || std::str::from_utf8(content).is_err()
+}
+
+/// A unified diff between two versions of a file, unless either side is
+/// binary or larger than `MAX_DIFF_SIZE`.
+pub fn unified_diff(old: &[u8], new: &[u8], old_label: &str, new_label: &str) -> FileDiff {
+    if old.len() > MAX_DIFF_SIZE || new.len() > MAX_DIFF_SIZE {
+        return FileDiff::TooLarge;
+    }
+    if is_binary(old) || is_binary(new) {
+        return FileDiff::Binary;
+    }
+    let (Ok(old), Ok(new)) = (std::str::from_utf8(old), std::str::from_utf8(new)) else {
+        return FileDiff::Binary;
+    };
+
+    FileDiff::Text(
+        TextDiff::from_lines(old, new)
+            .unified_diff()
+            .context_radius(3)
+            .header(old_label, new_label)
+            .to_string(),
+    )
+}
+
+/// Prints a `unified_diff` result, or a one-line note when there is no text
+/// diff to show.
+pub fn print_file_diff(diff: &FileDiff, old_label: &str, new_label: &str) {
+    match diff {
+        FileDiff::Text(text) => print!("{}", text),
+        FileDiff::Binary => println!("Binary files {} and {} differ", old_label, new_label),
+        FileDiff::TooLarge => println!(
+            "Files {} and {} differ (larger than {} bytes, not diffed)",
+            old_label, new_label, MAX_DIFF_SIZE
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unified_diff() {
+        let FileDiff::Text(diff) = unified_diff(b"a\nb\nc\n", b"a\nB\nc\n", "a/x.js", "b/x.js")
+        else {
+            panic!("expected a text diff");
+        };
+        assert!(diff.starts_with("--- a/x.js\n+++ b/x.js\n"));
+        assert!(diff.contains("-b\n+B\n"));
+        assert_eq!(unified_diff(b"\0\x01", b"text", "a", "b"), FileDiff::Binary);
+
+        let large = vec![b'a'; MAX_DIFF_SIZE + 1];
+        assert_eq!(unified_diff(&large, b"a", "a", "b"), FileDiff::TooLarge);
+    }
+}
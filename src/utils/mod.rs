@@ -1,3 +1,4 @@
 pub mod compression;
+pub mod diff;
 pub mod fs;
 pub mod progress;